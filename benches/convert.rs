@@ -0,0 +1,105 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use cygwin_fs::CygRoot;
+
+const CYGWIN_PATHS: &[&str] = &[
+    "/usr/include/stdio.h",
+    "/home/user/src/project/src/main.c",
+    "/cygdrive/c/Program Files/Tool/include/tool.h",
+    "relative/dir/file.o",
+];
+
+const NATIVE_PATHS: &[&str] = &[
+    "C:\\cygwin64\\usr\\include\\stdio.h",
+    "C:\\cygwin64\\home\\user\\src\\project\\src\\main.c",
+    "C:\\Program Files\\Tool\\include\\tool.h",
+    "relative/dir/file.o",
+];
+
+fn cygroot() -> CygRoot {
+    CygRoot::from(PathBuf::from("C:\\cygwin64"), true)
+}
+
+fn bench_to_native(c: &mut Criterion) {
+    let cygroot = cygroot();
+    c.bench_function("convert_path_to_native", |b| b.iter(|| {
+        for path in CYGWIN_PATHS {
+            black_box(cygroot.convert_path_to_native(black_box(path)));
+        }
+    }));
+    c.bench_function("convert_path_to_native_into", |b| {
+        let mut out = OsString::new();
+        b.iter(|| {
+            for path in CYGWIN_PATHS {
+                cygroot.convert_path_to_native_into(black_box(path), &mut out);
+                black_box(&out);
+            }
+        })
+    });
+}
+
+fn bench_to_cygwin(c: &mut Criterion) {
+    let cygroot = cygroot();
+    c.bench_function("convert_path_to_cygwin", |b| b.iter(|| {
+        for path in NATIVE_PATHS {
+            black_box(cygroot.convert_path_to_cygwin(black_box(Path::new(path))));
+        }
+    }));
+    c.bench_function("convert_path_to_cygwin_into", |b| {
+        let mut out = String::new();
+        b.iter(|| {
+            for path in NATIVE_PATHS {
+                cygroot.convert_path_to_cygwin_into(black_box(Path::new(path)), &mut out);
+                black_box(&out);
+            }
+        })
+    });
+    c.bench_function("convert_path_to_cygwin_cow", |b| b.iter(|| {
+        for path in NATIVE_PATHS {
+            black_box(cygroot.convert_path_to_cygwin_cow(black_box(Path::new(path))));
+        }
+    }));
+}
+
+fn bench_resolve(c: &mut Criterion) {
+    let cygroot = CygRoot::from(PathBuf::new(), false);
+    c.bench_function("resolve_path outside Cygwin", |b| b.iter(|| {
+        for path in NATIVE_PATHS {
+            black_box(cygroot.resolve_path(black_box(Path::new(path))));
+        }
+    }));
+    c.bench_function("resolve_path_cow outside Cygwin", |b| b.iter(|| {
+        for path in NATIVE_PATHS {
+            black_box(cygroot.resolve_path_cow(black_box(Path::new(path))));
+        }
+    }));
+
+    // Regular files, so that every path is looked at, and none is a symlink
+    let root = std::env::temp_dir().join("cygwin_fs-bench");
+    let files = ["usr/include/stdio.h", "home/user/src/project/src/main.c"];
+    for file in files.iter() {
+        let native = root.join(file);
+        fs::create_dir_all(native.parent().unwrap()).unwrap();
+        fs::write(&native, b"").unwrap();
+    }
+    let cygroot = CygRoot::from(root.clone(), true);
+    let native_paths: Vec<PathBuf> = files.iter().map(|file| root.join(file)).collect();
+    c.bench_function("resolve_path of native paths under Cygwin", |b| b.iter(|| {
+        for path in native_paths.iter() {
+            black_box(cygroot.resolve_path(black_box(path)));
+        }
+    }));
+    c.bench_function("resolve_path_cow of native paths under Cygwin", |b| b.iter(|| {
+        for path in native_paths.iter() {
+            black_box(cygroot.resolve_path_cow(black_box(path)));
+        }
+    }));
+    let _ = fs::remove_dir_all(&root);
+}
+
+criterion_group!(benches, bench_to_native, bench_to_cygwin, bench_resolve);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+//! Resolving paths without blocking an async executor, with the `tokio` feature.
+//!
+//! These do what their blocking counterparts do, on tokio's blocking thread pool,
+//! with a copy of the `CygRoot` and of the path.
+//! Dropping the futures (as on a timeout) is safe: the blocking call is left to finish, and its result dropped.
+
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use tokio::task;
+
+use crate::policy::SymlinkPolicy;
+use crate::{CygRoot, maybe_cygwin_symlink};
+
+/// Does what `maybe_cygwin_symlink()` does, without blocking.
+pub async fn maybe_cygwin_symlink_async(path: &Path) -> bool {
+    let path = PathBuf::from(path);
+    blocking(move || maybe_cygwin_symlink(&path)).await
+}
+
+impl CygRoot {
+    /// Does what `read_symlink_contents()` does, without blocking.
+    pub async fn read_symlink_contents_async(&self, path: &Path) -> Option<PathBuf> {
+        let (cygroot, path) = (self.clone(), PathBuf::from(path));
+        blocking(move || cygroot.read_symlink_contents(&path)).await
+    }
+
+    /// Does what `resolve_symlink()` does, without blocking.
+    pub async fn resolve_symlink_async(&self, path: &Path) -> PathBuf {
+        let (cygroot, path) = (self.clone(), PathBuf::from(path));
+        blocking(move || cygroot.resolve_symlink(&path)).await
+    }
+
+    /// Does what `resolve_path()` does, without blocking.
+    pub async fn resolve_path_async(&self, p: &Path) -> PathBuf {
+        self.resolve_path_with_policy_async(p, self.symlink_policy()).await
+    }
+
+    /// Does what `resolve_path_with_policy()` does, without blocking.
+    pub async fn resolve_path_with_policy_async(&self, p: &Path, policy: SymlinkPolicy) -> PathBuf {
+        // Nothing to look at, so nothing to wait for
+        if !self.running_under_cygwin() || policy == SymlinkPolicy::Never {
+            return self.resolve_path_with_policy(p, policy)
+        }
+        let (cygroot, p) = (self.clone(), PathBuf::from(p));
+        blocking(move || cygroot.resolve_path_with_policy(&p, policy)).await
+    }
+}
+
+/// Runs `f` on tokio's blocking thread pool, passing on a panic in it.
+async fn blocking<T, F>(f: F) -> T where T: Send + 'static, F: FnOnce() -> T + Send + 'static {
+    match task::spawn_blocking(f).await {
+        Ok(ret) => ret,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use tokio::runtime;
+
+use crate::CygRoot;
+use super::{maybe_cygwin_symlink_async, blocking};
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+#[test]
+fn runs_off_the_executor_thread() {
+    let executor_thread = thread::current().id();
+    assert_ne!(block_on(blocking(|| thread::current().id())), executor_thread);
+}
+
+#[test]
+fn resolves_without_blocking() {
+    fn assert_send<T: Send>(_: &T) {}
+    let cygroot = CygRoot::from(PathBuf::new(), false);
+    let future = cygroot.resolve_path_async(Path::new("/tmp"));
+    assert_send(&future);
+    assert_eq!(block_on(future), PathBuf::from("/tmp"));
+    assert!(!block_on(maybe_cygwin_symlink_async(Path::new("/nonexistent"))));
+}
+
+}
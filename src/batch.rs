@@ -0,0 +1,92 @@
+//! Resolving lists of paths (like those of a compilation database) at once.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::cache::CachingResolver;
+use crate::CygRoot;
+
+impl CygRoot {
+    /// Does what `resolve_path()` does for every path, returning the results in the same order.
+    ///
+    /// Paths given more than once are resolved once.
+    /// The other paths go through one `CachingResolver`, which remembers the files looked at for the whole list
+    /// without checking their modification time, so files changing during the call could be missed.
+    /// Only files met by several resolutions are looked at once, as directories with `SymlinkPolicy::AllComponents`;
+    /// under the default `SymlinkPolicy::FinalOnly`, distinct absolute paths share nothing.
+    /// With the `rayon` feature, paths are resolved in parallel.
+    pub fn resolve_paths<I, P>(&self, paths: I) -> Vec<PathBuf>
+            where I: IntoIterator<Item = P>, P: AsRef<Path> {
+        let mut resolver = CachingResolver::new(self, usize::MAX);
+        resolver.set_validate_mtime(false);
+        resolver.resolve_paths(paths)
+    }
+}
+
+impl CachingResolver<'_> {
+    /// Does what `resolve_path()` does for every path, returning the results in the same order.
+    /// Paths given more than once are resolved once.
+    /// With the `rayon` feature, paths are resolved in parallel.
+    pub fn resolve_paths<I, P>(&self, paths: I) -> Vec<PathBuf>
+            where I: IntoIterator<Item = P>, P: AsRef<Path> {
+        let paths: Vec<P> = paths.into_iter().collect();
+        let (unique_paths, indices) = dedup_paths(&paths);
+        let resolved = resolve_all(self, &unique_paths);
+        indices.into_iter().map(|i| resolved[i].clone()).collect()
+    }
+}
+
+/// Returns the distinct paths in the order first given, and the index among them of every path given.
+fn dedup_paths<P: AsRef<Path>>(paths: &[P]) -> (Vec<&Path>, Vec<usize>) {
+    let mut unique_paths = Vec::<&Path>::new();
+    let mut unique_index = HashMap::<&Path, usize>::new();
+    let mut indices = Vec::<usize>::with_capacity(paths.len());
+    for path in paths.iter() {
+        let path = path.as_ref();
+        let index = *unique_index.entry(path).or_insert_with(|| {
+            unique_paths.push(path);
+            unique_paths.len() - 1
+        });
+        indices.push(index);
+    }
+    (unique_paths, indices)
+}
+
+#[cfg(feature = "rayon")]
+fn resolve_all(resolver: &CachingResolver, paths: &[&Path]) -> Vec<PathBuf> {
+    paths.par_iter().map(|path| resolver.resolve_path(path)).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn resolve_all(resolver: &CachingResolver, paths: &[&Path]) -> Vec<PathBuf> {
+    paths.iter().map(|path| resolver.resolve_path(path)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::path::{Path, PathBuf};
+
+use crate::CygRoot;
+use super::dedup_paths;
+
+#[test]
+fn dedups_keeping_order() {
+    let paths = ["/b", "/a", "/b", "c", "/a"];
+    let (unique_paths, indices) = dedup_paths(&paths);
+    assert_eq!(unique_paths, vec![Path::new("/b"), Path::new("/a"), Path::new("c")]);
+    assert_eq!(indices, vec![0, 1, 0, 2, 1]);
+}
+
+#[test]
+fn resolves_in_input_order() {
+    let cygroot = CygRoot::from(PathBuf::new(), false);
+    let paths = vec![PathBuf::from("/b"), PathBuf::from("/a"), PathBuf::from("/b")];
+    assert_eq!(cygroot.resolve_paths(&paths), paths);
+    assert!(cygroot.resolve_paths(Vec::<PathBuf>::new()).is_empty());
+}
+
+}
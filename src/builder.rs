@@ -0,0 +1,306 @@
+//! Composing a `CygRoot` from discovery and explicit settings.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::charset::Charset;
+use crate::compat::CompatProfile;
+use crate::folders::PathStyle;
+use crate::pe::Flavor;
+use crate::policy::SymlinkPolicy;
+use crate::CygRoot;
+
+/// Why `CygRootBuilder::build()` refused the settings.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum BuildError {
+    /// The root is not an absolute native path, like `C:\cygwin64`.
+    RelativeRoot(PathBuf),
+    /// The mount point does not start with `/`, or is `/` itself.
+    InvalidMountPoint(String),
+    /// The directory mounted is not an absolute native path.
+    RelativeMountTarget(PathBuf),
+    /// The cygdrive prefix does not start with `/`.
+    InvalidCygdrivePrefix(String),
+    /// The symlink hop limit is zero, so no symlink could be followed.
+    ZeroSymlinkHops,
+    /// `PathStyle::Unix` was given as the output style, while output paths are native ones.
+    UnixOutputStyle,
+    /// The working directory does not start with `/`.
+    RelativeCwd(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::RelativeRoot(ref root) => write!(f, "root {} is not an absolute native path", root.display()),
+            BuildError::InvalidMountPoint(ref mount_point) => write!(f, "invalid mount point {:?}", mount_point),
+            BuildError::RelativeMountTarget(ref native) =>
+                write!(f, "mounted directory {} is not an absolute native path", native.display()),
+            BuildError::InvalidCygdrivePrefix(ref prefix) => write!(f, "cygdrive prefix {:?} does not start with /", prefix),
+            BuildError::ZeroSymlinkHops => write!(f, "symlink hop limit is zero"),
+            BuildError::UnixOutputStyle => write!(f, "output style can not be the Unix one"),
+            BuildError::RelativeCwd(ref cwd) => write!(f, "working directory {:?} does not start with /", cwd),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// Settings for a `CygRoot`, checked together by `build()`.
+///
+/// ```rust,ignore
+/// let cygroot = CygRootBuilder::discover()
+///     .cygdrive_prefix("/mnt")
+///     .mount("/data", PathBuf::from("D:\\data"))
+///     .build()?;
+/// ```
+#[derive(Clone,Debug)]
+pub struct CygRootBuilder {
+    base: CygRoot,
+    root: Option<PathBuf>,
+    load_fstab: bool,
+    mounts: Vec<(String, PathBuf)>,
+    cygdrive_prefix: Option<String>,
+    max_symlink_hops: Option<usize>,
+    output_style: Option<PathStyle>,
+    flavor: Option<Flavor>,
+    compat_profile: Option<CompatProfile>,
+    symlink_policy: Option<SymlinkPolicy>,
+    charset: Option<Charset>,
+    cwd: Option<String>,
+}
+
+impl CygRootBuilder {
+    /// Starts with no root, so that paths are not translated unless `root()` is given.
+    pub fn new() -> CygRootBuilder {
+        CygRootBuilder::from_cygroot(CygRoot::from(PathBuf::new(), false))
+    }
+
+    /// Starts with the root `CygRoot::new()` finds.
+    pub fn discover() -> CygRootBuilder {
+        CygRootBuilder::from_cygroot(CygRoot::new())
+    }
+
+    /// Starts with the settings of `cygroot`, like one returned by `CygRoot::discover()`.
+    pub fn from_cygroot(cygroot: CygRoot) -> CygRootBuilder {
+        CygRootBuilder {
+            base: cygroot,
+            root: None,
+            load_fstab: false,
+            mounts: Vec::new(),
+            cygdrive_prefix: None,
+            max_symlink_hops: None,
+            output_style: None,
+            flavor: None,
+            compat_profile: None,
+            symlink_policy: None,
+            charset: None,
+            cwd: None,
+        }
+    }
+
+    /// Uses `root` (like `C:\cygwin64`) as the Cygwin root, translating paths as if running under Cygwin.
+    /// What was discovered about the previous root (like the `cygwin1.dll` path) is dropped.
+    pub fn root(mut self, root: PathBuf) -> CygRootBuilder {
+        self.root = Some(root);
+        self
+    }
+
+    /// Adds the mounts and the cygdrive prefix of `/etc/fstab` of the root,
+    /// before the ones given explicitly.
+    pub fn load_fstab(mut self, load: bool) -> CygRootBuilder {
+        self.load_fstab = load;
+        self
+    }
+
+    /// Mounts the native directory `native` (like `D:\data`) at `mount_point` (like `/data`).
+    pub fn mount(mut self, mount_point: &str, native: PathBuf) -> CygRootBuilder {
+        self.mounts.push((String::from(mount_point), native));
+        self
+    }
+
+    /// Sets the prefix of paths to drives, like `/mnt` for `/mnt/c`.
+    pub fn cygdrive_prefix(mut self, prefix: &str) -> CygRootBuilder {
+        self.cygdrive_prefix = Some(String::from(prefix));
+        self
+    }
+
+    /// Sets how many symlinks are followed in a row before giving up.
+    pub fn max_symlink_hops(mut self, hops: usize) -> CygRootBuilder {
+        self.max_symlink_hops = Some(hops);
+        self
+    }
+
+    /// Sets how native paths are written: `PathStyle::Windows` or `PathStyle::Mixed`.
+    pub fn output_style(mut self, style: PathStyle) -> CygRootBuilder {
+        self.output_style = Some(style);
+        self
+    }
+
+    /// Marks the root as a Cygwin or an MSYS2 one.
+    pub fn flavor(mut self, flavor: Flavor) -> CygRootBuilder {
+        self.flavor = Some(flavor);
+        self
+    }
+
+    /// Translates paths as the given Cygwin generation does, instead of detecting it.
+    pub fn compat_profile(mut self, profile: CompatProfile) -> CygRootBuilder {
+        self.compat_profile = Some(profile);
+        self
+    }
+
+    /// Sets which symlinks `resolve_path()` follows.
+    pub fn symlink_policy(mut self, policy: SymlinkPolicy) -> CygRootBuilder {
+        self.symlink_policy = Some(policy);
+        self
+    }
+
+    /// Converts Cygwin byte paths with `charset`, regardless of the locale.
+    pub fn charset(mut self, charset: Charset) -> CygRootBuilder {
+        self.charset = Some(charset);
+        self
+    }
+
+    /// Sets the Cygwin working directory relative paths are resolved against.
+    pub fn cwd(mut self, cwd: &str) -> CygRootBuilder {
+        self.cwd = Some(String::from(cwd));
+        self
+    }
+
+    /// Checks the settings, and makes a `CygRoot` of them.
+    pub fn build(self) -> Result<CygRoot, BuildError> {
+        self.validate()?;
+        let mut cygroot = match self.root {
+            Some(root) => CygRoot::from(root, true),
+            None => self.base,
+        };
+        if let Some(flavor) = self.flavor {
+            cygroot.set_flavor(flavor);
+        }
+        if self.compat_profile.is_some() {
+            cygroot.set_compat_profile(self.compat_profile);
+        }
+        if self.load_fstab {
+            cygroot.load_fstab();
+        }
+        for (mount_point, native) in self.mounts {
+            cygroot.add_mount(&mount_point, native);
+        }
+        if self.cygdrive_prefix.is_some() {
+            cygroot.set_cygdrive_prefix(self.cygdrive_prefix);
+        }
+        if let Some(hops) = self.max_symlink_hops {
+            cygroot.set_max_symlink_hops(hops);
+        }
+        if let Some(style) = self.output_style {
+            cygroot.set_output_style(style);
+        }
+        if let Some(policy) = self.symlink_policy {
+            cygroot.set_symlink_policy(policy);
+        }
+        if self.charset.is_some() {
+            cygroot.set_charset(self.charset);
+        }
+        if let Some(cwd) = self.cwd {
+            cygroot.set_cwd(Some(&cwd));
+        }
+        Ok(cygroot)
+    }
+
+    fn validate(&self) -> Result<(), BuildError> {
+        if let Some(ref root) = self.root {
+            if !is_native_absolute(root) {
+                return Err(BuildError::RelativeRoot(root.clone()));
+            }
+        }
+        for (mount_point, native) in self.mounts.iter() {
+            if !mount_point.starts_with('/') || mount_point.trim_end_matches('/').is_empty() {
+                return Err(BuildError::InvalidMountPoint(mount_point.clone()));
+            }
+            if !is_native_absolute(native) {
+                return Err(BuildError::RelativeMountTarget(native.clone()));
+            }
+        }
+        if let Some(ref prefix) = self.cygdrive_prefix {
+            if !prefix.starts_with('/') {
+                return Err(BuildError::InvalidCygdrivePrefix(prefix.clone()));
+            }
+        }
+        if self.max_symlink_hops == Some(0) {
+            return Err(BuildError::ZeroSymlinkHops);
+        }
+        if self.output_style == Some(PathStyle::Unix) {
+            return Err(BuildError::UnixOutputStyle);
+        }
+        if let Some(ref cwd) = self.cwd {
+            if !cwd.starts_with('/') {
+                return Err(BuildError::RelativeCwd(cwd.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for CygRootBuilder {
+    fn default() -> CygRootBuilder {
+        CygRootBuilder::new()
+    }
+}
+
+impl CygRoot {
+    /// Returns a `CygRootBuilder` starting with the settings of this one.
+    pub fn into_builder(self) -> CygRootBuilder {
+        CygRootBuilder::from_cygroot(self)
+    }
+}
+
+/// Whether `path` is a native path with a drive and a root (`C:\x`, `C:/x`), or a UNC one (`\\server\share`).
+fn is_native_absolute(path: &Path) -> bool {
+    let path_b = path.as_os_str().as_encoded_bytes();
+    let is_slash = |b: u8| b == b'/' || b == b'\\';
+    (path_b.len() >= 3 && path_b[0].is_ascii_alphabetic() && path_b[1] == b':' && is_slash(path_b[2]))
+        || (path_b.len() >= 3 && is_slash(path_b[0]) && is_slash(path_b[1]) && !is_slash(path_b[2]))
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::path::PathBuf;
+
+use crate::folders::PathStyle;
+use super::{CygRootBuilder, BuildError};
+
+#[test]
+fn builds_valid_settings() {
+    let built = CygRootBuilder::new()
+        .root(PathBuf::from("C:\\cygwin64"))
+        .mount("/data", PathBuf::from("D:/data"))
+        .mount("/share", PathBuf::from("\\\\server\\share"))
+        .cygdrive_prefix("/mnt")
+        .max_symlink_hops(8)
+        .output_style(PathStyle::Mixed)
+        .cwd("/home/user")
+        .build();
+    assert!(built.is_ok());
+}
+
+#[test]
+fn refuses_invalid_settings() {
+    let root = || CygRootBuilder::new().root(PathBuf::from("C:\\cygwin64"));
+    assert_eq!(CygRootBuilder::new().root(PathBuf::from("cygwin64")).build().err(),
+        Some(BuildError::RelativeRoot(PathBuf::from("cygwin64"))));
+    assert_eq!(root().mount("data", PathBuf::from("D:\\data")).build().err(),
+        Some(BuildError::InvalidMountPoint(String::from("data"))));
+    assert_eq!(root().mount("/", PathBuf::from("D:\\data")).build().err(),
+        Some(BuildError::InvalidMountPoint(String::from("/"))));
+    assert_eq!(root().mount("/data", PathBuf::from("D:data")).build().err(),
+        Some(BuildError::RelativeMountTarget(PathBuf::from("D:data"))));
+    assert_eq!(root().cygdrive_prefix("mnt").build().err(),
+        Some(BuildError::InvalidCygdrivePrefix(String::from("mnt"))));
+    assert_eq!(root().max_symlink_hops(0).build().err(), Some(BuildError::ZeroSymlinkHops));
+    assert_eq!(root().output_style(PathStyle::Unix).build().err(), Some(BuildError::UnixOutputStyle));
+    assert_eq!(root().cwd("home").build().err(), Some(BuildError::RelativeCwd(String::from("home"))));
+}
+
+}
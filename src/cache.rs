@@ -0,0 +1,338 @@
+//! Memoizing what resolution learns of files, for resolving many paths under the same directories.
+
+#[cfg(any(windows, test))]
+use std::collections::{HashMap, VecDeque};
+#[cfg(windows)]
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(windows)]
+use std::sync::{Mutex, MutexGuard};
+#[cfg(any(windows, test))]
+use std::time::SystemTime;
+
+#[cfg(windows)]
+use std::os::windows::fs::MetadataExt;
+#[cfg(windows)]
+use winapi::winnt::{FILE_ATTRIBUTE_SYSTEM, FILE_ATTRIBUTE_REPARSE_POINT};
+
+use crate::policy::SymlinkPolicy;
+use crate::CygRoot;
+
+/// Looks at files while resolving symlinks, either directly or through a cache.
+#[cfg(windows)]
+pub trait SymlinkProbe {
+    /// Whether the file could be a Cygwin symlink: a system file, or a WSL symlink if the profile reads them.
+    fn maybe_symlink(&self, path: &Path) -> bool;
+    /// The Cygwin target of the symlink, as `CygRoot::read_symlink_contents()` returns.
+    fn read_symlink(&self, path: &Path) -> Option<PathBuf>;
+}
+
+/// Resolves paths as the `CygRoot` it borrows does, remembering which files are symlinks and their targets,
+/// keyed by native path.
+///
+/// Entries are checked against the modification time of the file, so that changed symlinks are noticed,
+/// which costs one metadata query per file instead of an attribute query and a read.
+/// With `set_validate_mtime(false)`, the file system is not looked at again until `clear()` or `invalidate()`.
+///
+/// At most `capacity` files are remembered, forgetting the earliest remembered ones first.
+/// The cache is behind a lock, so a `CachingResolver` could be shared between threads, as in an `Arc`.
+#[derive(Debug)]
+pub struct CachingResolver<'a> {
+    cygroot: &'a CygRoot,
+    validate_mtime: bool,
+    #[cfg(windows)]
+    cache: Mutex<Cache>,
+}
+
+impl<'a> CachingResolver<'a> {
+    /// Resolves with `cygroot`, remembering at most `capacity` files.
+    #[cfg(windows)]
+    pub fn new(cygroot: &'a CygRoot, capacity: usize) -> CachingResolver<'a> {
+        CachingResolver {
+            cygroot,
+            validate_mtime: true,
+            cache: Mutex::new(Cache::new(capacity)),
+        }
+    }
+
+    /// Returns the `CygRoot` paths are resolved with.
+    pub fn cygroot(&self) -> &'a CygRoot {
+        self.cygroot
+    }
+
+    /// Whether remembered files are checked against their modification time.
+    pub fn validate_mtime(&self) -> bool {
+        self.validate_mtime
+    }
+
+    /// Sets whether remembered files are checked against their modification time (the default),
+    /// or trusted until `clear()` or `invalidate()`.
+    pub fn set_validate_mtime(&mut self, validate: bool) {
+        self.validate_mtime = validate;
+    }
+
+    /// Does what `CygRoot::resolve_path()` does, through the cache.
+    pub fn resolve_path(&self, p: &Path) -> PathBuf {
+        self.resolve_path_with_policy(p, self.cygroot.symlink_policy())
+    }
+
+    /// Does what `CygRoot::resolve_path_with_policy()` does, through the cache.
+    #[cfg(windows)]
+    pub fn resolve_path_with_policy(&self, p: &Path, policy: SymlinkPolicy) -> PathBuf {
+        self.cygroot.resolve_path_probed(p, policy, self)
+    }
+
+    /// Forgets everything remembered.
+    #[cfg(windows)]
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Forgets what is remembered of the native path `path`, like `C:\cygwin\symlink`.
+    #[cfg(windows)]
+    pub fn invalidate(&self, path: &Path) {
+        self.lock().remove(path);
+    }
+
+    /// How many files are remembered.
+    #[cfg(windows)]
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether no files are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[cfg(windows)]
+    fn lock(&self) -> MutexGuard<'_, Cache> {
+        // The cache is consistent between calls, so a panic elsewhere does not spoil it
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Nothing is remembered on other systems, where `CygRoot` does not look at files
+#[cfg(not(windows))]
+impl<'a> CachingResolver<'a> {
+    /// Resolves with `cygroot`; `capacity` is unused, as nothing is remembered.
+    pub fn new(cygroot: &'a CygRoot, _capacity: usize) -> CachingResolver<'a> {
+        CachingResolver { cygroot, validate_mtime: true }
+    }
+    /// Does what `CygRoot::resolve_path_with_policy()` does.
+    pub fn resolve_path_with_policy(&self, p: &Path, policy: SymlinkPolicy) -> PathBuf {
+        self.cygroot.resolve_path_with_policy(p, policy)
+    }
+    /// Does nothing, as nothing is remembered.
+    pub fn clear(&self) {}
+    /// Does nothing, as nothing is remembered.
+    pub fn invalidate(&self, _path: &Path) {}
+    /// Returns 0, as nothing is remembered.
+    pub fn len(&self) -> usize { 0 }
+}
+
+#[cfg(windows)]
+impl SymlinkProbe for CachingResolver<'_> {
+    fn maybe_symlink(&self, path: &Path) -> bool {
+        let (modified, maybe_from_metadata) =
+            if self.validate_mtime {
+                match fs::symlink_metadata(path) {
+                    Err(_) => {
+                        self.invalidate(path);
+                        return false
+                    },
+                    Ok(metadata) => {
+                        // The reparse tag of a reparse point is not in the metadata
+                        let maybe = maybe_cygwin_symlink_metadata(&metadata);
+                        let decided = maybe || !is_reparse_point_metadata(&metadata);
+                        (metadata.modified().ok(), if decided { Some(maybe) } else { None })
+                    },
+                }
+            } else {
+                (None, None)
+            };
+        if let Some(maybe) = self.lock().maybe_symlink(path, modified) {
+            return maybe
+        }
+        let maybe = maybe_from_metadata.unwrap_or_else(|| self.cygroot.could_be_symlink(path));
+        self.lock().insert(PathBuf::from(path), Entry { modified, maybe_symlink: maybe, target: None });
+        maybe
+    }
+
+    fn read_symlink(&self, path: &Path) -> Option<PathBuf> {
+        if let Some(target) = self.lock().target(path) {
+            return target
+        }
+        let target = self.cygroot.read_symlink_contents(path);
+        self.lock().set_target(path, target.clone());
+        target
+    }
+}
+
+/// Does what `maybe_cygwin_symlink()` does, with the attributes of already queried metadata.
+#[cfg(windows)]
+pub fn maybe_cygwin_symlink_metadata(metadata: &fs::Metadata) -> bool {
+    (metadata.file_attributes() & FILE_ATTRIBUTE_SYSTEM) != 0
+}
+
+/// Whether already queried metadata is of a reparse point, like a WSL symlink or a junction.
+#[cfg(windows)]
+pub fn is_reparse_point_metadata(metadata: &fs::Metadata) -> bool {
+    (metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT) != 0
+}
+
+/// What is remembered of a file.
+#[cfg(any(windows, test))]
+#[derive(Clone,Debug)]
+struct Entry {
+    /// The modification time the entry is valid for, if checked.
+    modified: Option<SystemTime>,
+    maybe_symlink: bool,
+    /// The symlink target, if the file has been read.
+    target: Option<Option<PathBuf>>,
+}
+
+#[cfg(any(windows, test))]
+#[derive(Debug)]
+struct Cache {
+    capacity: usize,
+    entries: HashMap<PathBuf, Entry>,
+    /// Paths in the order they were remembered, to forget the earliest ones first.
+    order: VecDeque<PathBuf>,
+}
+
+#[cfg(any(windows, test))]
+impl Cache {
+    fn new(capacity: usize) -> Cache {
+        Cache {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Whether the file could be a symlink, if remembered for the modification time `modified`.
+    fn maybe_symlink(&self, path: &Path, modified: Option<SystemTime>) -> Option<bool> {
+        match self.entries.get(path) {
+            Some(entry) if entry.modified == modified => Some(entry.maybe_symlink),
+            _ => None,
+        }
+    }
+
+    fn target(&self, path: &Path) -> Option<Option<PathBuf>> {
+        self.entries.get(path).and_then(|entry| entry.target.clone())
+    }
+
+    fn set_target(&mut self, path: &Path, target: Option<PathBuf>) {
+        if let Some(entry) = self.entries.get_mut(path) {
+            entry.target = Some(target);
+        }
+    }
+
+    /// Remembers the entry, replacing the one of the same path, and forgetting the earliest one if full.
+    fn insert(&mut self, path: PathBuf, entry: Entry) {
+        if self.capacity == 0 {
+            return
+        }
+        if self.entries.insert(path.clone(), entry).is_some() {
+            return
+        }
+        self.order.push_back(path);
+        while self.entries.len() > self.capacity {
+            match self.order.pop_front() {
+                None => break,
+                Some(earliest) => { self.entries.remove(&earliest); },
+            }
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if self.entries.remove(path).is_some() {
+            self.order.retain(|p| p != path);
+        }
+    }
+
+    #[cfg(windows)]
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::CygRoot;
+use super::{CachingResolver, Cache, Entry};
+
+fn entry(maybe_symlink: bool, modified_secs: Option<u64>) -> Entry {
+    Entry {
+        modified: modified_secs.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        maybe_symlink,
+        target: None,
+    }
+}
+
+#[test]
+fn remembers_until_modified() {
+    let mut cache = Cache::new(10);
+    let link = Path::new("C:\\cygwin\\link");
+    let at = |secs| Some(UNIX_EPOCH + Duration::from_secs(secs));
+    cache.insert(PathBuf::from(link), entry(true, Some(1)));
+    assert_eq!(cache.maybe_symlink(link, at(1)), Some(true));
+    assert_eq!(cache.maybe_symlink(link, at(2)), None);
+    assert_eq!(cache.target(link), None);
+    cache.set_target(link, Some(PathBuf::from("/tmp")));
+    assert_eq!(cache.target(link), Some(Some(PathBuf::from("/tmp"))));
+
+    cache.insert(PathBuf::from(link), entry(false, Some(2)));
+    assert_eq!(cache.maybe_symlink(link, at(2)), Some(false));
+    assert_eq!(cache.target(link), None);
+    assert_eq!(cache.order.len(), 1);
+}
+
+#[test]
+fn forgets_earliest_when_full() {
+    let mut cache = Cache::new(2);
+    for name in ["a", "b", "c"].iter() {
+        cache.insert(PathBuf::from(name), entry(true, None));
+    }
+    assert_eq!(cache.maybe_symlink(Path::new("a"), None), None);
+    assert_eq!(cache.maybe_symlink(Path::new("b"), None), Some(true));
+    assert_eq!(cache.maybe_symlink(Path::new("c"), None), Some(true));
+
+    cache.remove(Path::new("b"));
+    cache.insert(PathBuf::from("d"), entry(false, None));
+    assert_eq!(cache.entries.len(), 2);
+    assert_eq!(cache.order, vec![PathBuf::from("c"), PathBuf::from("d")]);
+
+    let mut no_cache = Cache::new(0);
+    no_cache.insert(PathBuf::from("a"), entry(true, None));
+    assert!(no_cache.entries.is_empty());
+}
+
+#[test]
+fn shares_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<CachingResolver<'static>>();
+
+    let cygroot = CygRoot::from(PathBuf::from("F:\\cygwin"), true);
+    let mut resolver = CachingResolver::new(&cygroot, 16);
+    resolver.set_validate_mtime(false);
+    let resolver = &resolver;
+    let resolved = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..2).map(|_| scope.spawn(move || resolver.resolve_path(Path::new("/tmp/x")))).collect();
+        threads.into_iter().map(|thread| thread.join().unwrap()).collect::<Vec<_>>()
+    });
+    let native = cygroot.resolve_path(Path::new("/tmp/x"));
+    assert_eq!(resolved, vec![native.clone(), native.clone()]);
+    // Both threads looked at the same file, remembered once; other systems do not look at files
+    assert_eq!(resolver.len(), if cfg!(windows) { 1 } else { 0 });
+    resolver.invalidate(&native);
+    assert!(resolver.is_empty());
+}
+
+}
@@ -0,0 +1,253 @@
+//! The charset Cygwin converts byte names with, as chosen by the locale.
+
+use encoding_rs::{Encoding, DecoderResult, EncoderResult};
+use encoding_rs::{UTF_8, UTF_16LE, UTF_16BE, SHIFT_JIS, EUC_JP, EUC_KR};
+
+use crate::codepage::Codepage;
+use crate::env::EnvSource;
+
+/// A charset of Cygwin byte paths (like `UTF-8` in `en_US.UTF-8`, or `KOI8-R` in `ru_RU.KOI8-R`),
+/// as returned by `CygRoot::charset()`.
+///
+/// Bytes invalid in the charset stand for U+F000 + byte in native names, as in Cygwin.
+/// Characters the charset has no bytes for are written as `0x0E` (`^N`) followed by their UTF-8 bytes.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Charset {
+    encoding: &'static Encoding,
+}
+
+impl Charset {
+    /// The charset Cygwin uses when the locale names none, or an unknown one.
+    pub fn utf8() -> Charset {
+        Charset { encoding: UTF_8 }
+    }
+
+    /// Looks up a charset by name, like `UTF-8`, `CP1252`, `KOI8-R` or `SJIS`.
+    pub fn from_name(name: &str) -> Option<Charset> {
+        let name = name.to_ascii_lowercase();
+        let encoding = match name.as_str() {
+            "utf8" => Some(UTF_8),
+            "cp932" => Some(SHIFT_JIS),
+            "eucjp" => Some(EUC_JP),
+            "euckr" => Some(EUC_KR),
+            _ => Encoding::for_label_no_replacement(name.as_bytes()),
+        };
+        match encoding {
+            Some(encoding) if encoding != UTF_16LE && encoding != UTF_16BE => Some(Charset { encoding }),
+            _ => None,
+        }
+    }
+
+    /// Returns the charset of a locale like `ru_RU.KOI8-R` or `C.UTF-8@cjknarrow`,
+    /// or `None` if it names no charset, or an unknown one.
+    pub fn from_locale(locale: &str) -> Option<Charset> {
+        let locale = match locale.find('@') {
+            Some(i) => &locale[..i],
+            None => locale,
+        };
+        match locale.find('.') {
+            Some(i) => Charset::from_name(&locale[i + 1..]),
+            None => None,
+        }
+    }
+
+    /// Returns the charset of the first of `LC_ALL`, `LC_CTYPE` and `LANG` set in `env`, as Cygwin does,
+    /// falling back to UTF-8.
+    pub fn from_env(env: &dyn EnvSource) -> Charset {
+        for name in ["LC_ALL", "LC_CTYPE", "LANG"].iter() {
+            if let Some(locale) = env.var_os(name) {
+                if !locale.is_empty() {
+                    return Charset::from_locale(&locale.to_string_lossy()).unwrap_or_else(Charset::utf8);
+                }
+            }
+        }
+        Charset::utf8()
+    }
+
+    /// Returns the canonical name of the charset, like `UTF-8` or `KOI8-R`.
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    /// Decodes a Cygwin byte path.
+    pub fn decode(&self, data: &[u8]) -> String {
+        let mut ret = String::with_capacity(data.len());
+        let mut rest = data;
+        if self.encoding != UTF_8 {
+            while let Some(i) = rest.iter().position(|&b| b == 0x0e) {
+                match utf8_char_at(&rest[i + 1..]) {
+                    Some(ch) => {
+                        self.decode_into(&rest[..i], &mut ret);
+                        ret.push(ch);
+                        rest = &rest[i + 1 + ch.len_utf8()..];
+                    },
+                    None => {
+                        self.decode_into(&rest[..i + 1], &mut ret);
+                        rest = &rest[i + 1..];
+                    },
+                }
+            }
+        }
+        self.decode_into(rest, &mut ret);
+        ret
+    }
+
+    fn decode_into(&self, data: &[u8], ret: &mut String) {
+        let mut decoder = self.encoding.new_decoder_without_bom_handling();
+        let mut pos = 0;
+        loop {
+            let src = &data[pos..];
+            ret.reserve(decoder.max_utf8_buffer_length_without_replacement(src.len()).unwrap_or(src.len() * 3));
+            let (result, read) = decoder.decode_to_string_without_replacement(src, ret, true);
+            pos += read;
+            match result {
+                DecoderResult::InputEmpty => return,
+                DecoderResult::OutputFull => {},
+                DecoderResult::Malformed(bad_len, after_len) => {
+                    let bad_end = pos - after_len as usize;
+                    for &b in data[bad_end - bad_len as usize..bad_end].iter() {
+                        ret.push(raw_byte_char(b));
+                    }
+                    // Bytes consumed after the malformed ones are decoded anew
+                    pos = bad_end;
+                    decoder = self.encoding.new_decoder_without_bom_handling();
+                },
+            }
+        }
+    }
+
+    /// Encodes a name into a Cygwin byte path.
+    pub fn encode(&self, name: &str) -> Vec<u8> {
+        let mut ret = Vec::<u8>::with_capacity(name.len());
+        let mut rest = name;
+        while let Some(i) = rest.find(|ch| raw_byte_of_char(ch).is_some()) {
+            self.encode_into(&rest[..i], &mut ret);
+            let ch = rest[i..].chars().next().unwrap_or('\0');
+            ret.push(raw_byte_of_char(ch).unwrap_or(0));
+            rest = &rest[i + ch.len_utf8()..];
+        }
+        self.encode_into(rest, &mut ret);
+        ret
+    }
+
+    fn encode_into(&self, name: &str, ret: &mut Vec<u8>) {
+        if self.encoding == UTF_8 {
+            ret.extend_from_slice(name.as_bytes());
+            return;
+        }
+        let mut encoder = self.encoding.new_encoder();
+        let mut src = name;
+        loop {
+            ret.reserve(encoder.max_buffer_length_from_utf8_without_replacement(src.len()).unwrap_or(src.len() * 4));
+            let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(src, ret, true);
+            src = &src[read..];
+            match result {
+                EncoderResult::InputEmpty => return,
+                EncoderResult::OutputFull => {},
+                EncoderResult::Unmappable(ch) => {
+                    let mut buf = [0u8; 4];
+                    ret.push(0x0e);
+                    ret.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                },
+            }
+        }
+    }
+}
+
+impl Default for Charset {
+    fn default() -> Charset {
+        Charset::utf8()
+    }
+}
+
+impl From<Codepage> for Charset {
+    fn from(codepage: Codepage) -> Charset {
+        Charset::from_name(&format!("cp{}", codepage.number())).unwrap_or_else(Charset::utf8)
+    }
+}
+
+/// The private use character an invalid byte (0x80 and above) is decoded to.
+fn raw_byte_char(b: u8) -> char {
+    ::std::char::from_u32(0xf000 + b as u32).unwrap_or('\u{fffd}')
+}
+
+fn raw_byte_of_char(ch: char) -> Option<u8> {
+    let c = ch as u32;
+    if (0xf080..0xf100).contains(&c) {
+        Some((c - 0xf000) as u8)
+    } else {
+        None
+    }
+}
+
+/// Decodes the non-ASCII UTF-8 character `data` starts with.
+fn utf8_char_at(data: &[u8]) -> Option<char> {
+    let len = match data.first() {
+        Some(&b) if (0xc2..0xe0).contains(&b) => 2,
+        Some(&b) if (0xe0..0xf0).contains(&b) => 3,
+        Some(&b) if (0xf0..0xf5).contains(&b) => 4,
+        _ => return None,
+    };
+    data.get(..len)
+        .and_then(|seq| ::std::str::from_utf8(seq).ok())
+        .and_then(|s| s.chars().next())
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+
+use crate::codepage::Codepage;
+use super::Charset;
+
+#[test]
+fn parses_locales() {
+    assert_eq!(Charset::from_locale("ru_RU.KOI8-R").map(|c| c.name()), Some("KOI8-R"));
+    assert_eq!(Charset::from_locale("C.CP1252").map(|c| c.name()), Some("windows-1252"));
+    assert_eq!(Charset::from_locale("ja_JP.SJIS").map(|c| c.name()), Some("Shift_JIS"));
+    assert_eq!(Charset::from_locale("en_US.utf8@cjknarrow").map(|c| c.name()), Some("UTF-8"));
+    assert_eq!(Charset::from_locale("C"), None);
+    assert_eq!(Charset::from_locale("en_US.UTF-16"), None);
+    assert_eq!(Charset::from(Codepage::Cp932).name(), "Shift_JIS");
+}
+
+#[test]
+fn takes_charset_from_env() {
+    let mut env = HashMap::new();
+    assert_eq!(Charset::from_env(&env), Charset::utf8());
+    env.insert(String::from("LANG"), OsString::from("ru_RU.KOI8-R"));
+    assert_eq!(Charset::from_env(&env).name(), "KOI8-R");
+    env.insert(String::from("LC_CTYPE"), OsString::from("C.CP1251"));
+    assert_eq!(Charset::from_env(&env).name(), "windows-1251");
+    env.insert(String::from("LC_ALL"), OsString::from("C"));
+    assert_eq!(Charset::from_env(&env), Charset::utf8());
+}
+
+#[test]
+fn converts_in_charset() {
+    let koi8 = Charset::from_name("KOI8-R").unwrap();
+    assert_eq!(koi8.decode(b"/tmp/\xf0\xd2\xc9"), "/tmp/При");
+    assert_eq!(koi8.encode("/tmp/При"), b"/tmp/\xf0\xd2\xc9");
+}
+
+#[test]
+fn keeps_invalid_bytes_as_private_use_chars() {
+    let utf8 = Charset::utf8();
+    assert_eq!(utf8.decode(b"a\xffb"), "a\u{f0ff}b");
+    assert_eq!(utf8.encode("a\u{f0ff}b"), b"a\xffb");
+    let sjis = Charset::from_name("SJIS").unwrap();
+    assert_eq!(sjis.decode(b"\x93\xfa\xa0"), "日\u{f0a0}");
+    assert_eq!(sjis.encode("日\u{f0a0}"), b"\x93\xfa\xa0");
+}
+
+#[test]
+fn escapes_unrepresentable_chars_as_utf8() {
+    let cp1252 = Charset::from_name("CP1252").unwrap();
+    assert_eq!(cp1252.encode("caf\u{e9} \u{65e5}"), b"caf\xe9 \x0e\xe6\x97\xa5");
+    assert_eq!(cp1252.decode(b"caf\xe9 \x0e\xe6\x97\xa5"), "caf\u{e9} \u{65e5}");
+    assert_eq!(cp1252.decode(b"\x0ea"), "\u{e}a");
+}
+
+}
@@ -0,0 +1,151 @@
+//! Decoding of symlink targets written without a byte order mark.
+
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, UTF_8, SHIFT_JIS};
+use encoding_rs::{WINDOWS_1250, WINDOWS_1251, WINDOWS_1252, WINDOWS_1253, WINDOWS_1254};
+use encoding_rs::{WINDOWS_1255, WINDOWS_1256, WINDOWS_1257, WINDOWS_1258};
+
+/// A charset of symlink targets without a byte order mark, as set with `CygRoot::set_symlink_codepage()`.
+///
+/// Cygwin 1.7 and later write such targets in UTF-8, while Cygwin 1.5 used the system ANSI codepage.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+pub enum Codepage {
+    #[default]
+    Utf8,
+    /// Central European.
+    Windows1250,
+    /// Cyrillic.
+    Windows1251,
+    /// Western European.
+    Windows1252,
+    /// Greek.
+    Windows1253,
+    /// Turkish.
+    Windows1254,
+    /// Hebrew.
+    Windows1255,
+    /// Arabic.
+    Windows1256,
+    /// Baltic.
+    Windows1257,
+    /// Vietnamese.
+    Windows1258,
+    /// Japanese Shift JIS, with the Microsoft extensions.
+    Cp932,
+}
+
+impl Codepage {
+    /// Returns the codepage with the given Windows identifier, like 1251 or 65001.
+    pub fn from_number(number: u32) -> Option<Codepage> {
+        match number {
+            65001 => Some(Codepage::Utf8),
+            1250 => Some(Codepage::Windows1250),
+            1251 => Some(Codepage::Windows1251),
+            1252 => Some(Codepage::Windows1252),
+            1253 => Some(Codepage::Windows1253),
+            1254 => Some(Codepage::Windows1254),
+            1255 => Some(Codepage::Windows1255),
+            1256 => Some(Codepage::Windows1256),
+            1257 => Some(Codepage::Windows1257),
+            1258 => Some(Codepage::Windows1258),
+            932 => Some(Codepage::Cp932),
+            _ => None,
+        }
+    }
+
+    /// Returns the Windows identifier of the codepage.
+    pub fn number(self) -> u32 {
+        match self {
+            Codepage::Utf8 => 65001,
+            Codepage::Windows1250 => 1250,
+            Codepage::Windows1251 => 1251,
+            Codepage::Windows1252 => 1252,
+            Codepage::Windows1253 => 1253,
+            Codepage::Windows1254 => 1254,
+            Codepage::Windows1255 => 1255,
+            Codepage::Windows1256 => 1256,
+            Codepage::Windows1257 => 1257,
+            Codepage::Windows1258 => 1258,
+            Codepage::Cp932 => 932,
+        }
+    }
+
+    /// Returns the ANSI codepage of the system, if it is a supported one.
+    #[cfg(windows)]
+    pub fn ansi() -> Option<Codepage> {
+        let number = unsafe { ::kernel32::GetACP() };
+        Codepage::from_number(number)
+    }
+
+    /// Returns UTF-8 on other systems, which have no ANSI codepage.
+    #[cfg(not(windows))]
+    pub fn ansi() -> Option<Codepage> {
+        Some(Codepage::Utf8)
+    }
+
+    /// Decodes `data`, or returns `None` if it has sequences invalid in the codepage.
+    pub fn decode(self, data: &[u8]) -> Option<Cow<'_, str>> {
+        self.encoding().decode_without_bom_handling_and_without_replacement(data)
+    }
+
+    /// Decodes `data`, replacing invalid sequences with U+FFFD.
+    pub fn decode_lossy(self, data: &[u8]) -> Cow<'_, str> {
+        self.encoding().decode_without_bom_handling(data).0
+    }
+
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            Codepage::Utf8 => UTF_8,
+            Codepage::Windows1250 => WINDOWS_1250,
+            Codepage::Windows1251 => WINDOWS_1251,
+            Codepage::Windows1252 => WINDOWS_1252,
+            Codepage::Windows1253 => WINDOWS_1253,
+            Codepage::Windows1254 => WINDOWS_1254,
+            Codepage::Windows1255 => WINDOWS_1255,
+            Codepage::Windows1256 => WINDOWS_1256,
+            Codepage::Windows1257 => WINDOWS_1257,
+            Codepage::Windows1258 => WINDOWS_1258,
+            Codepage::Cp932 => SHIFT_JIS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::Codepage;
+
+#[test]
+fn maps_numbers() {
+    assert_eq!(Codepage::from_number(1251), Some(Codepage::Windows1251));
+    assert_eq!(Codepage::from_number(932), Some(Codepage::Cp932));
+    assert_eq!(Codepage::from_number(437), None);
+    assert_eq!(Codepage::Windows1258.number(), 1258);
+}
+
+#[test]
+fn decodes_single_byte_codepages() {
+    assert_eq!(Codepage::Windows1251.decode_lossy(b"/tmp/\xcf\xf0\xe8\xe2\xe5\xf2"), "/tmp/Привет");
+    assert_eq!(Codepage::Windows1252.decode_lossy(b"caf\xe9"), "café");
+    assert_eq!(Codepage::Windows1250.decode_lossy(b"\x8a"), "Š");
+}
+
+#[test]
+fn decodes_cp932() {
+    assert_eq!(Codepage::Cp932.decode_lossy(b"\x93\xfa\x96\x7b/\x87\x40"), "日本/①");
+}
+
+#[test]
+fn decodes_utf8_lossily() {
+    assert_eq!(Codepage::Utf8.decode_lossy("日本".as_bytes()), "日本");
+    assert_eq!(Codepage::Utf8.decode_lossy(b"a\xffb"), "a\u{fffd}b");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn ansi_is_utf8_elsewhere() {
+    assert_eq!(Codepage::ansi(), Some(Codepage::Utf8));
+}
+
+}
@@ -0,0 +1,137 @@
+//! Differences between generations of the Cygwin runtime.
+
+use crate::pe::FileVersion;
+
+/// A way Cygwin stores symlinks.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum SymlinkFormat {
+    /// A system file with `!<symlink>`, a UTF-16 byte order mark, and the target, as written by Cygwin 1.7 and later.
+    MagicUtf16,
+    /// A system file with `!<symlink>` and the target in a byte charset, without a byte order mark,
+    /// as written by Cygwin 1.5 in the ANSI codepage.
+    MagicBytes,
+    /// A WSL symlink reparse point (`IO_REPARSE_TAG_LX_SYMLINK`), as written by Cygwin 3.1 and later.
+    Wsl,
+}
+
+/// What a generation of the Cygwin runtime does differently, as returned by `CygRoot::compat_profile()`.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub struct CompatProfile {
+    /// The symlink formats to recognize.
+    pub symlink_formats: Vec<SymlinkFormat>,
+    /// Mounts present without `/etc/fstab`, as `(mount point, directory under the root)`,
+    /// like `("/usr/bin", "/bin")`.
+    pub default_mounts: Vec<(String, String)>,
+    /// The prefix of paths to drives, like `/cygdrive` in `/cygdrive/c`.
+    pub cygdrive_prefix: String,
+    /// Whether characters not allowed in Windows names (`"*:<>?|`, control ones, and trailing dots and spaces)
+    /// are stored as their U+F000 private use area counterparts, as Cygwin 1.7 and later do.
+    pub special_char_mapping: bool,
+}
+
+impl CompatProfile {
+    /// Cygwin 1.5 and earlier, with symlink targets in the ANSI codepage.
+    pub fn cygwin_1_5() -> CompatProfile {
+        CompatProfile {
+            symlink_formats: vec![SymlinkFormat::MagicBytes],
+            default_mounts: default_mounts(),
+            cygdrive_prefix: String::from("/cygdrive"),
+            special_char_mapping: false,
+        }
+    }
+
+    /// Cygwin 1.7 to 3.0, with UTF-16 symlink targets and special character mapping.
+    pub fn cygwin_1_7() -> CompatProfile {
+        CompatProfile {
+            symlink_formats: vec![SymlinkFormat::MagicUtf16, SymlinkFormat::MagicBytes],
+            default_mounts: default_mounts(),
+            cygdrive_prefix: String::from("/cygdrive"),
+            special_char_mapping: true,
+        }
+    }
+
+    /// Cygwin 3.1 and later, which also create WSL symlinks.
+    pub fn cygwin_3_1() -> CompatProfile {
+        CompatProfile {
+            symlink_formats: vec![SymlinkFormat::Wsl, SymlinkFormat::MagicUtf16, SymlinkFormat::MagicBytes],
+            ..CompatProfile::cygwin_1_7()
+        }
+    }
+
+    /// The profile of the given `cygwin1.dll` version.
+    pub fn for_version(version: FileVersion) -> CompatProfile {
+        if version < FileVersion::new(1, 7, 0, 0) {
+            CompatProfile::cygwin_1_5()
+        } else if version < FileVersion::new(3, 1, 0, 0) {
+            CompatProfile::cygwin_1_7()
+        } else {
+            CompatProfile::cygwin_3_1()
+        }
+    }
+
+    /// Whether symlinks of the given format are recognized.
+    pub fn reads(&self, format: SymlinkFormat) -> bool {
+        self.symlink_formats.contains(&format)
+    }
+}
+
+impl Default for CompatProfile {
+    /// The profile of the current Cygwin.
+    fn default() -> CompatProfile {
+        CompatProfile::cygwin_3_1()
+    }
+}
+
+fn default_mounts() -> Vec<(String, String)> {
+    vec![
+        (String::from("/usr/bin"), String::from("/bin")),
+        (String::from("/usr/lib"), String::from("/lib")),
+    ]
+}
+
+/// The reparse tag of WSL symlinks.
+#[cfg(any(windows, test))]
+pub const IO_REPARSE_TAG_LX_SYMLINK: u32 = 0xa000_001d;
+
+/// Extracts the UTF-8 target of a WSL symlink from a `REPARSE_DATA_BUFFER`
+/// (as returned by `FSCTL_GET_REPARSE_POINT`): a tag, a data length, a reserved word,
+/// then a version (2) and the target.
+#[cfg(any(windows, test))]
+pub fn wsl_symlink_target(reparse_data: &[u8]) -> Option<&[u8]> {
+    if reparse_data.len() < 12 {
+        return None;
+    }
+    let u32_at = |i: usize| reparse_data[i] as u32 | (reparse_data[i + 1] as u32) << 8
+        | (reparse_data[i + 2] as u32) << 16 | (reparse_data[i + 3] as u32) << 24;
+    let data_length = reparse_data[4] as usize | (reparse_data[5] as usize) << 8;
+    if u32_at(0) != IO_REPARSE_TAG_LX_SYMLINK || u32_at(8) != 2 || data_length < 4 {
+        return None;
+    }
+    reparse_data.get(12..8 + data_length)
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+
+#[test]
+fn selects_profile_by_version() {
+    assert_eq!(CompatProfile::for_version(FileVersion::new(1, 5, 25, 0)), CompatProfile::cygwin_1_5());
+    assert_eq!(CompatProfile::for_version(FileVersion::new(1, 7, 35, 0)), CompatProfile::cygwin_1_7());
+    assert_eq!(CompatProfile::for_version(FileVersion::new(3, 0, 7, 0)), CompatProfile::cygwin_1_7());
+    assert_eq!(CompatProfile::for_version(FileVersion::new(3, 4, 9, 0)), CompatProfile::cygwin_3_1());
+    assert!(CompatProfile::cygwin_3_1().reads(SymlinkFormat::Wsl));
+    assert!(!CompatProfile::cygwin_1_5().reads(SymlinkFormat::MagicUtf16));
+}
+
+#[test]
+fn reads_wsl_symlink_reparse_data() {
+    let mut data = vec![0x1d, 0x00, 0x00, 0xa0, 4 + 4, 0, 0, 0, 2, 0, 0, 0];
+    data.extend_from_slice(b"/tmp");
+    assert_eq!(wsl_symlink_target(&data), Some(&b"/tmp"[..]));
+    data[0] = 0x0c;
+    assert_eq!(wsl_symlink_target(&data), None);
+}
+
+}
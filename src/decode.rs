@@ -0,0 +1,228 @@
+//! Strict decoding of the target stored in a Cygwin symlink file, after the `!<symlink>` magic.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+#[cfg(any(windows, test))]
+use std::io::{self, Read};
+
+use crate::codepage::Codepage;
+
+/// The magic a Cygwin symlink file starts with.
+#[cfg(any(windows, test))]
+pub const SYMLINK_MAGIC: &[u8] = b"!<symlink>";
+
+/// The largest Cygwin symlink file: the magic, a byte order mark,
+/// and a UTF-16 target of up to `PATH_MAX` (4096) characters with a NUL.
+pub const MAX_SYMLINK_SIZE: u64 = 10 + 2 + 2 * (4096 + 1);
+
+/// Reads what follows the magic of a Cygwin symlink file `file_len` bytes long.
+/// Only the magic is read from files that are not symlinks,
+/// and nothing from ones larger than `MAX_SYMLINK_SIZE`.
+/// Returns `Ok(None)` for such files.
+#[cfg(any(windows, test))]
+pub fn read_symlink_data<R: Read>(reader: &mut R, file_len: u64) -> io::Result<Option<Vec<u8>>> {
+    if file_len > MAX_SYMLINK_SIZE || file_len < SYMLINK_MAGIC.len() as u64 {
+        return Ok(None);
+    }
+    let mut magic = [0u8; 10];
+    reader.read_exact(&mut magic)?;
+    if magic != SYMLINK_MAGIC {
+        return Ok(None);
+    }
+    let mut data = vec![0u8; (file_len - SYMLINK_MAGIC.len() as u64) as usize];
+    reader.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Why a symlink target could not be decoded.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum DecodeError {
+    /// There is no target, or only a byte order mark.
+    Empty,
+    /// The UTF-16 target ends in half a code unit.
+    OddLength,
+    /// The target without a byte order mark is not valid in the given codepage.
+    Malformed(Codepage),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Empty => write!(f, "empty symlink target"),
+            DecodeError::OddLength => write!(f, "UTF-16 symlink target has an odd number of bytes"),
+            DecodeError::Malformed(codepage) => write!(f, "symlink target is not valid in codepage {}", codepage.number()),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Decodes a symlink target: UTF-16 after a byte order mark (`FF FE` or `FE FF`), or `codepage` without one.
+/// The target ends at the first NUL, if any.
+///
+/// Unlike decoding into a `String`, nothing is replaced: unpaired surrogates,
+/// which Windows allows in names, are kept (as WTF-8 outside of Windows).
+pub fn decode_symlink_target(data: &[u8], codepage: Codepage) -> Result<OsString, DecodeError> {
+    let big_endian = data.starts_with(&[0xfe, 0xff]);
+    let little_endian = data.starts_with(&[0xff, 0xfe]);
+    if !big_endian && !little_endian {
+        let bytes = match data.iter().position(|&b| b == 0) {
+            Some(nul) => &data[..nul],
+            None => data,
+        };
+        if bytes.is_empty() {
+            return Err(DecodeError::Empty);
+        }
+        return match codepage.decode(bytes) {
+            Some(s) => Ok(OsString::from(s.into_owned())),
+            None => Err(DecodeError::Malformed(codepage)),
+        };
+    }
+
+    let mut wide = Vec::<u16>::with_capacity(data.len() / 2);
+    let mut pairs = data[2..].chunks(2);
+    for pair in pairs.by_ref() {
+        if pair.len() < 2 {
+            return Err(DecodeError::OddLength);
+        }
+        let unit = if big_endian {
+            (pair[0] as u16) << 8 | pair[1] as u16
+        } else {
+            (pair[1] as u16) << 8 | pair[0] as u16
+        };
+        if unit == 0 {
+            break;
+        }
+        wide.push(unit);
+    }
+    if wide.is_empty() {
+        return Err(DecodeError::Empty);
+    }
+    Ok(os_string_from_wide(&wide))
+}
+
+#[cfg(windows)]
+fn os_string_from_wide(wide: &[u16]) -> OsString {
+    use std::os::windows::ffi::OsStringExt;
+    OsString::from_wide(wide)
+}
+
+/// Encodes `wide` as WTF-8: UTF-8, with unpaired surrogates encoded as if they were characters.
+#[cfg(unix)]
+fn os_string_from_wide(wide: &[u16]) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    let mut bytes = Vec::<u8>::with_capacity(wide.len());
+    for unit in ::std::char::decode_utf16(wide.iter().cloned()) {
+        match unit {
+            Ok(ch) => {
+                let mut buf = [0u8; 4];
+                bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            },
+            Err(err) => {
+                let surrogate = err.unpaired_surrogate();
+                bytes.push(0xe0 | (surrogate >> 12) as u8);
+                bytes.push(0x80 | ((surrogate >> 6) & 0x3f) as u8);
+                bytes.push(0x80 | (surrogate & 0x3f) as u8);
+            },
+        }
+    }
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(any(windows, unix)))]
+fn os_string_from_wide(wide: &[u16]) -> OsString {
+    OsString::from(String::from_utf16_lossy(wide))
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::ffi::OsString;
+
+use std::io::{self, Cursor, Read};
+
+use crate::codepage::Codepage;
+use super::{DecodeError, decode_symlink_target, read_symlink_data, MAX_SYMLINK_SIZE};
+
+/// Counts the bytes read, to check what is left unread.
+struct CountingReader<R> {
+    inner: R,
+    read: usize,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn reads_symlink_data_after_magic() {
+    let file = b"!<symlink>\xff\xfea\0";
+    let mut reader = Cursor::new(&file[..]);
+    assert_eq!(read_symlink_data(&mut reader, file.len() as u64).unwrap(), Some(vec![0xff, 0xfe, b'a', 0]));
+}
+
+#[test]
+fn reads_only_magic_of_other_files() {
+    let file = vec![b'x'; 4096];
+    let mut reader = CountingReader { inner: Cursor::new(&file[..]), read: 0 };
+    assert_eq!(read_symlink_data(&mut reader, file.len() as u64).unwrap(), None);
+    assert_eq!(reader.read, 10);
+}
+
+#[test]
+fn reads_nothing_of_large_or_short_files() {
+    let file = b"!<symlink>/tmp";
+    let mut reader = CountingReader { inner: Cursor::new(&file[..]), read: 0 };
+    assert_eq!(read_symlink_data(&mut reader, MAX_SYMLINK_SIZE + 1).unwrap(), None);
+    assert_eq!(read_symlink_data(&mut reader, 4).unwrap(), None);
+    assert_eq!(reader.read, 0);
+}
+
+#[test]
+fn fails_on_files_shorter_than_their_length() {
+    let file = b"!<symlink>/tmp";
+    let mut reader = Cursor::new(&file[..]);
+    assert!(read_symlink_data(&mut reader, 100).is_err());
+}
+
+#[test]
+fn decodes_utf16_with_either_byte_order() {
+    assert_eq!(decode_symlink_target(&[0xff, 0xfe, b'a', 0, b'b', 0], Codepage::Utf8), Ok(OsString::from("ab")));
+    assert_eq!(decode_symlink_target(&[0xfe, 0xff, 0, b'a', 0, b'b'], Codepage::Utf8), Ok(OsString::from("ab")));
+}
+
+#[test]
+fn stops_at_nul() {
+    assert_eq!(decode_symlink_target(&[0xff, 0xfe, b'a', 0, 0, 0, b'x'], Codepage::Utf8), Ok(OsString::from("a")));
+    assert_eq!(decode_symlink_target(b"/tmp\0junk", Codepage::Utf8), Ok(OsString::from("/tmp")));
+}
+
+#[test]
+fn rejects_short_and_odd_data() {
+    assert_eq!(decode_symlink_target(b"", Codepage::Utf8), Err(DecodeError::Empty));
+    assert_eq!(decode_symlink_target(&[0xff, 0xfe], Codepage::Utf8), Err(DecodeError::Empty));
+    assert_eq!(decode_symlink_target(&[0xff, 0xfe, b'a'], Codepage::Utf8), Err(DecodeError::OddLength));
+    assert_eq!(decode_symlink_target(&[0xff, 0xfe, b'a', 0, b'b'], Codepage::Utf8), Err(DecodeError::OddLength));
+    assert_eq!(decode_symlink_target(b"a", Codepage::Utf8), Ok(OsString::from("a")));
+}
+
+#[test]
+fn rejects_malformed_bytes() {
+    assert_eq!(decode_symlink_target(b"a\xffb", Codepage::Utf8), Err(DecodeError::Malformed(Codepage::Utf8)));
+    assert_eq!(decode_symlink_target(b"\xcf\xf0", Codepage::Windows1251), Ok(OsString::from("Пр")));
+}
+
+#[cfg(unix)]
+#[test]
+fn keeps_unpaired_surrogates_as_wtf8() {
+    use std::os::unix::ffi::OsStrExt;
+    let target = decode_symlink_target(&[0xff, 0xfe, b'a', 0, 0x00, 0xd8], Codepage::Utf8).unwrap();
+    assert_eq!(target.as_bytes(), &[b'a', 0xed, 0xa0, 0x80]);
+}
+
+}
@@ -0,0 +1,478 @@
+//! Strategies for finding the Cygwin root directory.
+
+#[cfg(any(windows, test))]
+use std::ffi::OsString;
+#[cfg(any(windows, test))]
+use std::fs::File;
+#[cfg(any(windows, test))]
+use std::io::Read;
+use std::path::{Path,PathBuf};
+
+#[cfg(any(windows, test))]
+use crate::env::EnvSource;
+
+/// A way of finding the Cygwin root, tried by `CygRoot::discover` in the given order.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum RootStrategy {
+    /// Takes the root from an environment variable, like `CYGWIN_ROOT`.
+    EnvOverride(String),
+    /// Looks up `cygwin1.dll` in `PATH`, and takes the path two dirs upper, as Cygwin keeps the dll in `/bin`.
+    /// This is what `CygRoot::new()` does.
+    PathScan,
+    /// Takes the first directory having `bin\cygwin1.dll`.
+    WellKnownDirs(Vec<PathBuf>),
+    /// Takes `last-install-dir` from the first readable `setup.rc` of Cygwin setup.
+    SetupRc(Vec<PathBuf>),
+    /// Takes `rootdir` of the `Cygwin\setup` key from a `.reg` file exported by `regedit`.
+    RegFile(PathBuf),
+}
+
+impl RootStrategy {
+    /// `CYGWIN_ROOT` override, `PATH` scan, then `C:\cygwin64` and `C:\cygwin`.
+    pub fn defaults() -> Vec<RootStrategy> {
+        vec![
+            RootStrategy::EnvOverride(String::from("CYGWIN_ROOT")),
+            RootStrategy::PathScan,
+            RootStrategy::WellKnownDirs(vec![PathBuf::from("C:\\cygwin64"), PathBuf::from("C:\\cygwin")]),
+        ]
+    }
+}
+
+/// What a strategy has found.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum DiscoveryOutcome {
+    /// The native path to the Cygwin root.
+    Found(PathBuf),
+    /// Why the strategy has not found a root.
+    Missed(String),
+}
+
+/// A strategy tried by `CygRoot::discover`, and what it has found.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct DiscoveryAttempt {
+    pub strategy: RootStrategy,
+    pub outcome: DiscoveryOutcome,
+    /// The `cygwin1.dll` the root was found by (for `PathScan`), or the one in its `bin`, if any.
+    pub cygwin_dll: Option<PathBuf>,
+}
+
+/// Tries `strategies` in order until one finds a root, looking up environment variables in `env`.
+/// Returns every strategy tried; the last one is the successful one, if any.
+#[cfg(any(windows, test))]
+pub fn discover(strategies: &[RootStrategy], env: &dyn EnvSource) -> Vec<DiscoveryAttempt> {
+    let mut ret = Vec::with_capacity(strategies.len());
+    for strategy in strategies.iter() {
+        let (outcome, cygwin_dll) = try_strategy(strategy, env);
+        let (found, cygwin_dll) = match outcome {
+            DiscoveryOutcome::Found(ref root) => {
+                let root_cygwin_dll = root.join("bin").join("cygwin1.dll");
+                (true, cygwin_dll.or_else(|| if root_cygwin_dll.is_file() { Some(root_cygwin_dll) } else { None }))
+            },
+            DiscoveryOutcome::Missed(_) => (false, None),
+        };
+        ret.push(DiscoveryAttempt { strategy: strategy.clone(), outcome, cygwin_dll });
+        if found {
+            break;
+        }
+    }
+    ret
+}
+
+/// Returns the root found by the last of `attempts`, if any.
+#[cfg(any(windows, test))]
+pub fn found_root(attempts: &[DiscoveryAttempt]) -> Option<&Path> {
+    match attempts.last() {
+        Some(&DiscoveryAttempt { outcome: DiscoveryOutcome::Found(ref root), .. }) => Some(root.as_path()),
+        _ => None,
+    }
+}
+
+/// Returns the `cygwin1.dll` of the root found by the last of `attempts`, if any.
+#[cfg(any(windows, test))]
+pub fn found_cygwin_dll(attempts: &[DiscoveryAttempt]) -> Option<&Path> {
+    match found_root(attempts) {
+        None => None,
+        Some(_) => attempts.last().and_then(|attempt| attempt.cygwin_dll.as_ref()).map(|p| p.as_path()),
+    }
+}
+
+/// Returns the outcome, and the `cygwin1.dll` the root was derived from, if it was.
+#[cfg(any(windows, test))]
+fn try_strategy(strategy: &RootStrategy, env: &dyn EnvSource) -> (DiscoveryOutcome, Option<PathBuf>) {
+    let outcome = match *strategy {
+        RootStrategy::PathScan => return scan_path(env),
+        RootStrategy::EnvOverride(ref name) => try_env_override(name, env),
+        RootStrategy::WellKnownDirs(ref dirs) => try_well_known_dirs(dirs),
+        RootStrategy::SetupRc(ref files) => try_setup_rc_files(files),
+        RootStrategy::RegFile(ref file) => try_reg_file(file),
+    };
+    (outcome, None)
+}
+
+#[cfg(any(windows, test))]
+fn try_env_override(name: &str, env: &dyn EnvSource) -> DiscoveryOutcome {
+    match env.var_os(name) {
+        Some(ref root) if !root.is_empty() => {
+            let root = PathBuf::from(root);
+            if root.is_dir() {
+                DiscoveryOutcome::Found(root)
+            } else {
+                missed(format!("{} is set to {:?}, which is not a directory", name, root))
+            }
+        },
+        _ => missed(format!("{} is not set", name)),
+    }
+}
+
+#[cfg(any(windows, test))]
+fn try_well_known_dirs(dirs: &[PathBuf]) -> DiscoveryOutcome {
+    for dir in dirs.iter() {
+        if has_cygwin_dll(dir) {
+            return DiscoveryOutcome::Found(dir.clone());
+        }
+    }
+    missed(format!("none of {:?} has bin\\cygwin1.dll", dirs))
+}
+
+#[cfg(any(windows, test))]
+fn try_setup_rc_files(files: &[PathBuf]) -> DiscoveryOutcome {
+    let mut reasons = Vec::new();
+    for file in files.iter() {
+        let contents = match read_file_lossy(file) {
+            None => { reasons.push(format!("{:?} is unreadable", file)); continue },
+            Some(contents) => contents,
+        };
+        match setup_rc_value(&contents, "last-install-dir") {
+            None => reasons.push(format!("{:?} has no last-install-dir", file)),
+            Some(dir) => return found_if_has_cygwin_dll(PathBuf::from(dir), file),
+        }
+    }
+    if reasons.is_empty() {
+        missed(String::from("no setup.rc files given"))
+    } else {
+        missed(reasons.join("; "))
+    }
+}
+
+#[cfg(any(windows, test))]
+fn try_reg_file(file: &Path) -> DiscoveryOutcome {
+    let contents = match read_file_lossy(file) {
+        None => return missed(format!("{:?} is unreadable", file)),
+        Some(contents) => contents,
+    };
+    match reg_file_cygwin_rootdir(&contents) {
+        None => missed(format!("{:?} has no rootdir in a Cygwin\\setup key", file)),
+        Some(dir) => found_if_has_cygwin_dll(PathBuf::from(dir), file),
+    }
+}
+
+#[cfg(any(windows, test))]
+fn scan_path(env: &dyn EnvSource) -> (DiscoveryOutcome, Option<PathBuf>) {
+    let cygwin_dll_name = Path::new("cygwin1.dll");
+    match find_in_pathlist(&env.var_os("PATH"), cygwin_dll_name) {
+        None => (missed(String::from("PATH has no cygwin1.dll")), None),
+        Some(cygwin_dll_path) => match cygwin_dll_path.parent().and_then(|bin| bin.parent()) {
+            None => (missed(format!("{:?} is not in a subdirectory", cygwin_dll_path)), None),
+            Some(root) => (DiscoveryOutcome::Found(PathBuf::from(root)), Some(cygwin_dll_path.clone())),
+        },
+    }
+}
+
+#[cfg(any(windows, test))]
+fn missed(reason: String) -> DiscoveryOutcome {
+    DiscoveryOutcome::Missed(reason)
+}
+
+#[cfg(any(windows, test))]
+fn has_cygwin_dll(root: &Path) -> bool {
+    root.join("bin").join("cygwin1.dll").is_file()
+}
+
+#[cfg(any(windows, test))]
+fn found_if_has_cygwin_dll(root: PathBuf, source: &Path) -> DiscoveryOutcome {
+    if has_cygwin_dll(&root) {
+        DiscoveryOutcome::Found(root)
+    } else {
+        missed(format!("{:?} from {:?} has no bin\\cygwin1.dll", root, source))
+    }
+}
+
+#[cfg(any(windows, test))]
+fn find_in_pathlist(pathlist: &Option<OsString>, filename: &Path) -> Option<PathBuf> {
+    for dir in std::env::split_paths(pathlist.as_ref()?) {
+        let filepath = dir.join(filename);
+        if filepath.is_file() {
+            return Some(filepath);
+        }
+    }
+    None
+}
+
+/// What is there in a supposed Cygwin root, as reported by `CygRoot::validate()`.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct RootValidation {
+    /// The native path to the root that was checked.
+    pub root: PathBuf,
+    /// Whether `bin` is a directory.
+    pub has_bin_dir: bool,
+    /// Whether `etc` is a directory.
+    pub has_etc_dir: bool,
+    /// Whether `etc\fstab` is a file.
+    pub has_fstab: bool,
+    /// Whether `bin\bash.exe` is a file.
+    pub has_bash: bool,
+    /// The `cygwin1.dll` the root was found by, if any.
+    pub cygwin_dll: Option<PathBuf>,
+    /// Whether `cygwin_dll` is `bin\cygwin1.dll` of the root, as in a real install,
+    /// and not a copy bundled with some application.
+    pub cygwin_dll_in_bin: bool,
+}
+
+impl RootValidation {
+    /// Whether every check has passed, so the root looks like a complete install.
+    pub fn is_valid(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// Describes every failed check.
+    pub fn problems(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+        if self.root.as_os_str().is_empty() {
+            ret.push(String::from("no Cygwin root was found"));
+            return ret;
+        }
+        let checks = [
+            (self.has_bin_dir, "has no bin directory"),
+            (self.has_etc_dir, "has no etc directory"),
+            (self.has_fstab, "has no etc\\fstab"),
+            (self.has_bash, "has no bin\\bash.exe"),
+        ];
+        for &(passed, problem) in checks.iter() {
+            if !passed {
+                ret.push(format!("{:?} {}", self.root, problem));
+            }
+        }
+        match self.cygwin_dll {
+            None => ret.push(format!("{:?} has no bin\\cygwin1.dll", self.root)),
+            Some(ref cygwin_dll) if !self.cygwin_dll_in_bin =>
+                ret.push(format!("{:?} is not in the bin directory of {:?}", cygwin_dll, self.root)),
+            Some(_) => {},
+        }
+        ret
+    }
+}
+
+/// Checks the files of a supposed Cygwin root, and where its `cygwin1.dll` is.
+pub fn validate_root(root: &Path, cygwin_dll: Option<&Path>) -> RootValidation {
+    if root.as_os_str().is_empty() {
+        return RootValidation {
+            root: PathBuf::new(),
+            has_bin_dir: false,
+            has_etc_dir: false,
+            has_fstab: false,
+            has_bash: false,
+            cygwin_dll: None,
+            cygwin_dll_in_bin: false,
+        };
+    }
+    let bin = root.join("bin");
+    let root_cygwin_dll = bin.join("cygwin1.dll");
+    let cygwin_dll = match cygwin_dll {
+        Some(cygwin_dll) => Some(PathBuf::from(cygwin_dll)),
+        None => if root_cygwin_dll.is_file() { Some(root_cygwin_dll) } else { None },
+    };
+    let cygwin_dll_in_bin = match cygwin_dll {
+        None => false,
+        Some(ref cygwin_dll) => match cygwin_dll.parent() {
+            None => false,
+            Some(dir) => {
+                let dir_is_bin = dir.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.eq_ignore_ascii_case("bin"));
+                dir_is_bin && dir.parent() == Some(root) && cygwin_dll.is_file()
+            },
+        },
+    };
+    RootValidation {
+        root: PathBuf::from(root),
+        has_bin_dir: bin.is_dir(),
+        has_etc_dir: root.join("etc").is_dir(),
+        has_fstab: root.join("etc").join("fstab").is_file(),
+        has_bash: bin.join("bash.exe").is_file(),
+        cygwin_dll,
+        cygwin_dll_in_bin,
+    }
+}
+
+/// Reads a text file that is either UTF-16LE with a byte order mark (as `regedit` writes), or UTF-8.
+#[cfg(any(windows, test))]
+fn read_file_lossy(path: &Path) -> Option<String> {
+    let mut data = Vec::new();
+    File::open(path).and_then(|mut f| f.read_to_end(&mut data)).ok()?;
+    if data.starts_with(&[0xff, 0xfe]) {
+        let data16: Vec<u16> = data[2..].chunks(2)
+            .map(|pair| pair[0] as u16 | (*pair.get(1).unwrap_or(&0) as u16) << 8)
+            .collect();
+        Some(String::from_utf16_lossy(&data16))
+    } else {
+        Some(String::from_utf8_lossy(&data).into_owned())
+    }
+}
+
+/// Returns the value of `key` in the contents of `setup.rc`,
+/// where a key is on a line of its own, and its value is on the next, indented, line.
+#[cfg(any(windows, test))]
+pub fn setup_rc_value<'a>(setup_rc: &'a str, key: &str) -> Option<&'a str> {
+    let mut lines = setup_rc.lines();
+    while let Some(line) = lines.next() {
+        if line.trim_end() != key {
+            continue;
+        }
+        return match lines.next() {
+            Some(value) if value.starts_with(&['\t', ' '][..]) && !value.trim().is_empty() => Some(value.trim()),
+            _ => None,
+        };
+    }
+    None
+}
+
+/// Returns `rootdir` of `HKEY_LOCAL_MACHINE\SOFTWARE\Cygwin\setup` (or of the `HKEY_CURRENT_USER`
+/// and `WOW6432Node` ones) in the contents of a `.reg` file.
+#[cfg(any(windows, test))]
+pub fn reg_file_cygwin_rootdir(reg: &str) -> Option<String> {
+    let mut in_cygwin_setup_key = false;
+    for line in reg.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let key = line.trim_matches(&['[', ']'][..]).to_ascii_lowercase();
+            in_cygwin_setup_key =
+                (key.starts_with("hkey_local_machine\\") || key.starts_with("hkey_current_user\\"))
+                && key.ends_with("\\cygwin\\setup");
+            continue;
+        }
+        if !in_cygwin_setup_key {
+            continue;
+        }
+        let mut name_and_value = line.splitn(2, '=');
+        let name = name_and_value.next().unwrap_or("");
+        if !name.eq_ignore_ascii_case("\"rootdir\"") {
+            continue;
+        }
+        let value = name_and_value.next().unwrap_or("");
+        if !value.starts_with('"') || !value.ends_with('"') || value.len() < 2 {
+            continue;
+        }
+        let mut ret = String::with_capacity(value.len());
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(ch) = chars.next() {
+            if ch == '\\' {
+                if let Some(escaped) = chars.next() {
+                    ret.push(escaped);
+                }
+            } else {
+                ret.push(ch);
+            }
+        }
+        return Some(ret);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+use std::fs;
+
+fn temp_root(name: &str, with_dll: bool) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cygwin_fs-discovery-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    if with_dll {
+        File::create(dir.join("bin").join("cygwin1.dll")).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn tries_strategies_in_order() {
+    let root = temp_root("order", true);
+    let env_root = root.clone();
+    let env = move |name: &str| if name == "PATH" { Some(OsString::from(env_root.join("bin"))) } else { None };
+    let attempts = discover(&[
+        RootStrategy::EnvOverride(String::from("CYGWIN_ROOT")),
+        RootStrategy::PathScan,
+        RootStrategy::WellKnownDirs(vec![]),
+    ], &env);
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[0].outcome, DiscoveryOutcome::Missed(String::from("CYGWIN_ROOT is not set")));
+    assert_eq!(found_root(&attempts), Some(root.as_path()));
+}
+
+#[test]
+fn checks_well_known_dirs_for_cygwin_dll() {
+    let stray = temp_root("stray", false);
+    let root = temp_root("well-known", true);
+    let attempts = discover(&[RootStrategy::WellKnownDirs(vec![stray, root.clone()])], &|_: &str| None);
+    assert_eq!(found_root(&attempts), Some(root.as_path()));
+}
+
+#[test]
+fn remembers_cygwin_dll_found_in_path() {
+    let root = temp_root("dll", true);
+    let env_root = root.clone();
+    let env = move |name: &str| if name == "PATH" { Some(OsString::from(env_root.join("bin"))) } else { None };
+    let attempts = discover(&[RootStrategy::PathScan], &env);
+    assert_eq!(found_cygwin_dll(&attempts), Some(root.join("bin").join("cygwin1.dll").as_path()));
+}
+
+#[test]
+fn validates_complete_roots() {
+    let root = temp_root("complete", true);
+    fs::create_dir_all(root.join("etc")).unwrap();
+    File::create(root.join("etc").join("fstab")).unwrap();
+    File::create(root.join("bin").join("bash.exe")).unwrap();
+    let validation = validate_root(&root, None);
+    assert!(validation.cygwin_dll_in_bin);
+    assert_eq!(validation.problems(), Vec::<String>::new());
+    assert!(validation.is_valid());
+}
+
+#[test]
+fn detects_stray_cygwin_dll() {
+    let app = temp_root("stray-app", false);
+    fs::create_dir_all(app.join("lib")).unwrap();
+    File::create(app.join("lib").join("cygwin1.dll")).unwrap();
+    let validation = validate_root(&app, Some(&app.join("lib").join("cygwin1.dll")));
+    assert!(!validation.cygwin_dll_in_bin);
+    assert!(!validation.has_etc_dir);
+    assert!(!validation.has_bash);
+    assert_eq!(validation.problems().len(), 4);
+    assert!(!validate_root(Path::new(""), None).is_valid());
+}
+
+#[test]
+fn reads_setup_rc() {
+    let rc = "last-cache\n\tC:\\Users\\me\\Downloads\nlast-install-dir\n\tD:\\cyg64\nnet-method\n\tDirect\n";
+    assert_eq!(setup_rc_value(rc, "last-install-dir"), Some("D:\\cyg64"));
+    assert_eq!(setup_rc_value(rc, "last-mirror"), None);
+}
+
+#[test]
+fn reads_reg_file() {
+    let reg = "Windows Registry Editor Version 5.00\r\n\r\n\
+        [HKEY_LOCAL_MACHINE\\SOFTWARE\\Other\\setup]\r\n\"rootdir\"=\"C:\\\\other\"\r\n\r\n\
+        [HKEY_LOCAL_MACHINE\\SOFTWARE\\Cygwin\\setup]\r\n\"rootdir\"=\"C:\\\\cygwin64\"\r\n";
+    assert_eq!(reg_file_cygwin_rootdir(reg), Some(String::from("C:\\cygwin64")));
+    assert_eq!(reg_file_cygwin_rootdir("REGEDIT4\n"), None);
+}
+
+#[test]
+fn reports_unreadable_files() {
+    let attempts = discover(&[RootStrategy::RegFile(PathBuf::from("/nonexistent.reg"))], &|_: &str| None);
+    assert_eq!(found_root(&attempts), None);
+    match attempts[0].outcome {
+        DiscoveryOutcome::Missed(ref reason) => assert!(reason.contains("unreadable")),
+        _ => panic!(),
+    }
+}
+
+}
@@ -0,0 +1,141 @@
+//! Sources of environment variables, so that the process environment could be replaced by another one.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+
+/// Something environment variables could be looked up in.
+/// Names are compared ignoring ASCII case where possible, as on Windows.
+pub trait EnvSource {
+    /// Returns the value of the `name` variable, if set.
+    fn var_os(&self, name: &str) -> Option<OsString>;
+}
+
+/// The environment of the current process, as seen by `std::env::var_os`.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct ProcessEnv;
+
+impl EnvSource for ProcessEnv {
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        std::env::var_os(name)
+    }
+}
+
+impl EnvSource for HashMap<String, OsString> {
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        if let Some(value) = self.get(name) {
+            return Some(value.clone());
+        }
+        self.iter()
+            .find(|&(var_name, _)| var_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+}
+
+impl<F> EnvSource for F where F: Fn(&str) -> Option<OsString> {
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        self(name)
+    }
+}
+
+/// A captured environment, like the one of another process.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct EnvBlock {
+    vars: Vec<(OsString, OsString)>,
+}
+
+impl EnvBlock {
+    /// Captures the environment of the current process.
+    pub fn capture() -> EnvBlock {
+        EnvBlock::from_pairs(std::env::vars_os())
+    }
+
+    /// Captures the given variables of `env`, skipping unset ones.
+    pub fn snapshot(env: &dyn EnvSource, names: &[&str]) -> EnvBlock {
+        EnvBlock::from_pairs(names.iter().filter_map(|name| env.var_os(name).map(|value| (OsString::from(*name), value))))
+    }
+
+    /// Makes an environment of `(name, value)` pairs. For repeated names, the first one wins.
+    pub fn from_pairs<I, K, V>(pairs: I) -> EnvBlock
+            where I: IntoIterator<Item = (K, V)>, K: Into<OsString>, V: Into<OsString> {
+        EnvBlock {
+            vars: pairs.into_iter().map(|(name, value)| (name.into(), value.into())).collect(),
+        }
+    }
+
+    /// Parses a Windows environment block (as passed to `CreateProcessW`, or read from another process):
+    /// `name=value` strings, each terminated by a NUL, with an extra NUL at the end.
+    /// Names could start with `=`, as the per-drive current directories (`=C:=C:\dir`) do.
+    pub fn from_wide(block: &[u16]) -> EnvBlock {
+        let mut vars = Vec::new();
+        for entry in block.split(|&unit| unit == 0) {
+            if entry.is_empty() {
+                break;
+            }
+            let sep = match entry.iter().skip(1).position(|&unit| unit == b'=' as u16) {
+                None => continue,
+                Some(i) => i + 1,
+            };
+            vars.push((os_string_from_wide(&entry[..sep]), os_string_from_wide(&entry[sep + 1..])));
+        }
+        EnvBlock { vars }
+    }
+
+    /// Iterates over `(name, value)` pairs.
+    pub fn iter(&self) -> ::std::slice::Iter<'_, (OsString, OsString)> {
+        self.vars.iter()
+    }
+}
+
+impl EnvSource for EnvBlock {
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        self.vars.iter()
+            .find(|(var_name, _)| var_name.to_str().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+            .map(|(_, value)| value.clone())
+    }
+}
+
+#[cfg(windows)]
+fn os_string_from_wide(wide: &[u16]) -> OsString {
+    use std::os::windows::ffi::OsStringExt;
+    OsString::from_wide(wide)
+}
+
+#[cfg(not(windows))]
+fn os_string_from_wide(wide: &[u16]) -> OsString {
+    OsString::from(String::from_utf16_lossy(wide))
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+#[test]
+fn parses_wide_env_blocks() {
+    let block = EnvBlock::from_wide(&wide("=C:=C:\\dir\0Path=C:\\bin;D:\\bin\0HOME=/home/me\0\0ignored=1\0"));
+    assert_eq!(block.var_os("=C:"), Some(OsString::from("C:\\dir")));
+    assert_eq!(block.var_os("PATH"), Some(OsString::from("C:\\bin;D:\\bin")));
+    assert_eq!(block.var_os("home"), Some(OsString::from("/home/me")));
+    assert_eq!(block.var_os("ignored"), None);
+}
+
+#[test]
+fn looks_up_maps_ignoring_case() {
+    let mut map = HashMap::new();
+    map.insert(String::from("SystemRoot"), OsString::from("C:\\Windows"));
+    assert_eq!(map.var_os("SYSTEMROOT"), Some(OsString::from("C:\\Windows")));
+    assert_eq!(map.var_os("WINDIR"), None);
+}
+
+#[test]
+fn snapshots_given_variables() {
+    let env = |name: &str| if name == "A" { Some(OsString::from("1")) } else { None };
+    let block = EnvBlock::snapshot(&env, &["A", "B"]);
+    assert_eq!(block, EnvBlock::from_pairs(vec![("A", "1")]));
+}
+
+}
@@ -0,0 +1,182 @@
+//! Locations of Windows special folders, as printed by `cygpath -D`, `-H`, `-O`, `-P`, `-S`, `-W` and `-F`.
+
+#[cfg(any(windows, test))]
+use crate::env::EnvSource;
+#[cfg(any(windows, test))]
+use self::csidl::*;
+
+/// A Windows special folder.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum SpecialFolder {
+    /// The user's desktop, as in `cygpath -D`.
+    Desktop,
+    /// The desktop shared by all users, as in `cygpath -A -D`.
+    AllUsersDesktop,
+    /// The directory containing user profiles, like `C:\Users`, as in `cygpath -H`.
+    HomeRoot,
+    /// The user's documents, as in `cygpath -O`.
+    Documents,
+    /// The documents shared by all users, as in `cygpath -A -O`.
+    AllUsersDocuments,
+    /// The user's Start Menu programs, as in `cygpath -P`.
+    Programs,
+    /// The Start Menu programs of all users, as in `cygpath -A -P`.
+    AllUsersPrograms,
+    /// The system directory, like `C:\Windows\System32`, as in `cygpath -S`.
+    System,
+    /// The Windows directory, like `C:\Windows`, as in `cygpath -W`.
+    Windows,
+    /// A folder with a numeric `CSIDL_*` ID, as in `cygpath -F`.
+    Folder(u32),
+}
+
+/// How a path is written, as selected by `cygpath -w`, `-m` and `-u`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum PathStyle {
+    /// `C:\Users\user`.
+    Windows,
+    /// `C:/Users/user`.
+    Mixed,
+    /// `/cygdrive/c/Users/user`.
+    Unix,
+}
+
+/// The `CSIDL_*` folder IDs of `shlobj.h`.
+#[cfg(any(windows, test))]
+mod csidl {
+    pub const CSIDL_DESKTOP: u32 = 0x0000;
+    pub const CSIDL_PROGRAMS: u32 = 0x0002;
+    pub const CSIDL_PERSONAL: u32 = 0x0005;
+    pub const CSIDL_STARTUP: u32 = 0x0007;
+    pub const CSIDL_STARTMENU: u32 = 0x000b;
+    pub const CSIDL_DESKTOPDIRECTORY: u32 = 0x0010;
+    pub const CSIDL_FONTS: u32 = 0x0014;
+    pub const CSIDL_TEMPLATES: u32 = 0x0015;
+    pub const CSIDL_COMMON_STARTMENU: u32 = 0x0016;
+    pub const CSIDL_COMMON_PROGRAMS: u32 = 0x0017;
+    pub const CSIDL_COMMON_STARTUP: u32 = 0x0018;
+    pub const CSIDL_COMMON_DESKTOPDIRECTORY: u32 = 0x0019;
+    pub const CSIDL_APPDATA: u32 = 0x001a;
+    pub const CSIDL_LOCAL_APPDATA: u32 = 0x001c;
+    pub const CSIDL_COMMON_APPDATA: u32 = 0x0023;
+    pub const CSIDL_WINDOWS: u32 = 0x0024;
+    pub const CSIDL_SYSTEM: u32 = 0x0025;
+    pub const CSIDL_PROGRAM_FILES: u32 = 0x0026;
+    pub const CSIDL_PROFILE: u32 = 0x0028;
+    pub const CSIDL_PROGRAM_FILES_COMMON: u32 = 0x002b;
+    pub const CSIDL_COMMON_DOCUMENTS: u32 = 0x002e;
+}
+
+/// Computes the native path of a special folder from environment variables
+/// (`USERPROFILE`, `PUBLIC`, `ALLUSERSPROFILE`, `APPDATA`, `LOCALAPPDATA`, `SYSTEMROOT`, `ProgramFiles`...),
+/// looked up in `env`.
+/// Returns `None` for unknown folder IDs, and if the needed variables are unset.
+#[cfg(any(windows, test))]
+pub fn special_folder_path(folder: SpecialFolder, env: &dyn EnvSource) -> Option<String> {
+    let var = |name: &str| match env.var_os(name) {
+        Some(ref value) if !value.is_empty() => Some(value.to_string_lossy().into_owned()),
+        _ => None,
+    };
+    let public = || var("PUBLIC").or_else(|| var("ALLUSERSPROFILE"));
+    let windows = || var("SYSTEMROOT").or_else(|| var("WINDIR"));
+    let start_menu = || var("APPDATA").map(|p| join(&p, "Microsoft\\Windows\\Start Menu"));
+    let common_start_menu = || var("ProgramData").or_else(|| var("ALLUSERSPROFILE"))
+        .map(|p| join(&p, "Microsoft\\Windows\\Start Menu"));
+    match folder {
+        SpecialFolder::Desktop => special_folder_path(SpecialFolder::Folder(CSIDL_DESKTOPDIRECTORY), env),
+        SpecialFolder::AllUsersDesktop => special_folder_path(SpecialFolder::Folder(CSIDL_COMMON_DESKTOPDIRECTORY), env),
+        SpecialFolder::HomeRoot => var("USERPROFILE").and_then(|p| parent(&p).map(String::from)),
+        SpecialFolder::Documents => special_folder_path(SpecialFolder::Folder(CSIDL_PERSONAL), env),
+        SpecialFolder::AllUsersDocuments => special_folder_path(SpecialFolder::Folder(CSIDL_COMMON_DOCUMENTS), env),
+        SpecialFolder::Programs => special_folder_path(SpecialFolder::Folder(CSIDL_PROGRAMS), env),
+        SpecialFolder::AllUsersPrograms => special_folder_path(SpecialFolder::Folder(CSIDL_COMMON_PROGRAMS), env),
+        SpecialFolder::System => special_folder_path(SpecialFolder::Folder(CSIDL_SYSTEM), env),
+        SpecialFolder::Windows => special_folder_path(SpecialFolder::Folder(CSIDL_WINDOWS), env),
+        SpecialFolder::Folder(csidl) => match csidl {
+            CSIDL_DESKTOP | CSIDL_DESKTOPDIRECTORY => var("USERPROFILE").map(|p| join(&p, "Desktop")),
+            CSIDL_PERSONAL => var("USERPROFILE").map(|p| join(&p, "Documents")),
+            CSIDL_PROFILE => var("USERPROFILE"),
+            CSIDL_STARTMENU => start_menu(),
+            CSIDL_PROGRAMS => start_menu().map(|p| join(&p, "Programs")),
+            CSIDL_STARTUP => start_menu().map(|p| join(&p, "Programs\\Startup")),
+            CSIDL_TEMPLATES => var("APPDATA").map(|p| join(&p, "Microsoft\\Windows\\Templates")),
+            CSIDL_APPDATA => var("APPDATA"),
+            CSIDL_LOCAL_APPDATA => var("LOCALAPPDATA"),
+            CSIDL_COMMON_STARTMENU => common_start_menu(),
+            CSIDL_COMMON_PROGRAMS => common_start_menu().map(|p| join(&p, "Programs")),
+            CSIDL_COMMON_STARTUP => common_start_menu().map(|p| join(&p, "Programs\\Startup")),
+            CSIDL_COMMON_DESKTOPDIRECTORY => public().map(|p| join(&p, "Desktop")),
+            CSIDL_COMMON_DOCUMENTS => public().map(|p| join(&p, "Documents")),
+            CSIDL_COMMON_APPDATA => var("ProgramData").or_else(|| var("ALLUSERSPROFILE")),
+            CSIDL_WINDOWS => windows(),
+            CSIDL_SYSTEM => windows().map(|p| join(&p, "System32")),
+            CSIDL_FONTS => windows().map(|p| join(&p, "Fonts")),
+            CSIDL_PROGRAM_FILES => var("ProgramFiles"),
+            CSIDL_PROGRAM_FILES_COMMON => var("CommonProgramFiles"),
+            _ => None,
+        },
+    }
+}
+
+/// Joins Windows paths with a backslash, independently of the platform the crate is built for.
+#[cfg(any(windows, test))]
+fn join(dir: &str, rest: &str) -> String {
+    let mut ret = String::from(dir.trim_end_matches(&['\\', '/'][..]));
+    ret.push('\\');
+    ret.push_str(rest);
+    ret
+}
+
+/// Returns the directory part of a Windows path, unless it is a drive root.
+#[cfg(any(windows, test))]
+fn parent(path: &str) -> Option<&str> {
+    let path = path.trim_end_matches(&['\\', '/'][..]);
+    let sep = path.rfind(&['\\', '/'][..])?;
+    let dir = &path[..sep];
+    if dir.is_empty() || dir.ends_with(':') { None } else { Some(dir) }
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+use std::ffi::OsString;
+
+fn env(name: &str) -> Option<OsString> {
+    let value = match name {
+        "USERPROFILE" => "C:\\Users\\alice",
+        "PUBLIC" => "C:\\Users\\Public",
+        "ALLUSERSPROFILE" => "C:\\ProgramData",
+        "APPDATA" => "C:\\Users\\alice\\AppData\\Roaming",
+        "SYSTEMROOT" => "C:\\Windows",
+        _ => return None,
+    };
+    Some(OsString::from(value))
+}
+
+#[test]
+fn computes_user_folders() {
+    assert_eq!(special_folder_path(SpecialFolder::Desktop, &env).unwrap(), "C:\\Users\\alice\\Desktop");
+    assert_eq!(special_folder_path(SpecialFolder::Documents, &env).unwrap(), "C:\\Users\\alice\\Documents");
+    assert_eq!(special_folder_path(SpecialFolder::HomeRoot, &env).unwrap(), "C:\\Users");
+    assert_eq!(special_folder_path(SpecialFolder::Programs, &env).unwrap(),
+        "C:\\Users\\alice\\AppData\\Roaming\\Microsoft\\Windows\\Start Menu\\Programs");
+}
+
+#[test]
+fn computes_all_users_and_system_folders() {
+    assert_eq!(special_folder_path(SpecialFolder::AllUsersDesktop, &env).unwrap(), "C:\\Users\\Public\\Desktop");
+    assert_eq!(special_folder_path(SpecialFolder::AllUsersPrograms, &env).unwrap(),
+        "C:\\ProgramData\\Microsoft\\Windows\\Start Menu\\Programs");
+    assert_eq!(special_folder_path(SpecialFolder::System, &env).unwrap(), "C:\\Windows\\System32");
+    assert_eq!(special_folder_path(SpecialFolder::Folder(CSIDL_FONTS), &env).unwrap(), "C:\\Windows\\Fonts");
+}
+
+#[test]
+fn fails_on_unknown_folders_and_unset_variables() {
+    assert_eq!(special_folder_path(SpecialFolder::Folder(0x7777), &env), None);
+    assert_eq!(special_folder_path(SpecialFolder::Folder(CSIDL_LOCAL_APPDATA), &env), None);
+    assert_eq!(parent("C:\\"), None);
+}
+
+}
@@ -0,0 +1,95 @@
+//! Parsing of Cygwin's `/etc/fstab`.
+
+/// A line of `/etc/fstab`, like `C:/data /data ntfs binary 0 0`.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct FstabEntry {
+    /// The native directory, like `C:/data` (`none` for the `cygdrive` entry).
+    pub native: String,
+    /// Where it is mounted, like `/data`, or the cygdrive prefix for the `cygdrive` entry.
+    pub mount_point: String,
+    /// The file system type, like `ntfs`, or `cygdrive`.
+    pub fs_type: String,
+    /// The mount options, like `binary` and `posix=0`.
+    pub options: Vec<String>,
+}
+
+impl FstabEntry {
+    /// Whether the entry sets the cygdrive prefix, rather than mounting a directory.
+    pub fn is_cygdrive(&self) -> bool {
+        self.fs_type == "cygdrive"
+    }
+}
+
+/// Parses the contents of `/etc/fstab`, skipping comments and malformed lines.
+/// Spaces in paths are written as `\040`, as in Cygwin.
+pub fn parse_fstab(contents: &str) -> Vec<FstabEntry> {
+    let mut ret = Vec::new();
+    for line in contents.lines() {
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let mut fields = line.split_whitespace();
+        let (native, mount_point, fs_type) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(native), Some(mount_point), Some(fs_type)) => (native, mount_point, fs_type),
+            _ => continue,
+        };
+        let options = match fields.next() {
+            Some(options) => options.split(',').map(unescape_field).collect(),
+            None => Vec::new(),
+        };
+        ret.push(FstabEntry {
+            native: unescape_field(native),
+            mount_point: unescape_field(mount_point),
+            fs_type: unescape_field(fs_type),
+            options,
+        });
+    }
+    ret
+}
+
+/// Replaces octal escapes like `\040` with the characters they stand for.
+fn unescape_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut ret = Vec::<u8>::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() && bytes[i + 1..i + 4].iter().all(|b| (b'0'..=b'7').contains(b)) {
+            let value = bytes[i + 1..i + 4].iter().fold(0u32, |acc, b| acc * 8 + (b - b'0') as u32);
+            if value < 0x100 {
+                ret.push(value as u8);
+                i += 4;
+                continue;
+            }
+        }
+        ret.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&ret).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::parse_fstab;
+
+#[test]
+fn parses_mounts_and_cygdrive_prefix() {
+    let fstab = "# comment\n\
+        C:/cygwin64/bin /usr/bin ntfs binary,auto 0 0\n\
+        \n\
+        D:/My\\040Data /data ntfs binary 0 0 # trailing\n\
+        none / cygdrive binary,posix=0,user 0 0\n\
+        malformed\n";
+    let entries = parse_fstab(fstab);
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].native, "C:/cygwin64/bin");
+    assert_eq!(entries[0].mount_point, "/usr/bin");
+    assert_eq!(entries[0].options, vec!["binary", "auto"]);
+    assert_eq!(entries[1].native, "D:/My Data");
+    assert!(!entries[1].is_cygdrive());
+    assert!(entries[2].is_cygdrive());
+    assert_eq!(entries[2].mount_point, "/");
+}
+
+}
@@ -0,0 +1,83 @@
+//! A process-wide `CygRoot`, found once and shared by everything in the process.
+
+use std::mem;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+use crate::CygRoot;
+
+static GLOBAL: OnceLock<RwLock<Arc<CygRoot>>> = OnceLock::new();
+
+impl CygRoot {
+    /// The process-wide `CygRoot`, found with `CygRoot::new()` and `CygRoot::load_fstab()` on first use,
+    /// from any thread.
+    ///
+    /// The returned one stays as it is after `CygRoot::reload()` or `CygRoot::set_global()`;
+    /// call this again to see their changes.
+    pub fn global() -> Arc<CygRoot> {
+        let lock = GLOBAL.get_or_init(|| RwLock::new(Arc::new(discover())));
+        lock.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Finds Cygwin again as `CygRoot::global()` does, as after `PATH` or `/etc/fstab` changed,
+    /// and makes it the global one, returning it.
+    /// Settings of the previous one, as one installed with `CygRoot::set_global()`, are not kept.
+    /// `CygRoot::global()` keeps returning the previous one while it is being found.
+    pub fn reload() -> Arc<CygRoot> {
+        let cygroot = Arc::new(discover());
+        install(cygroot.clone());
+        cygroot
+    }
+
+    /// Makes `cygroot` the global one, as in tests, returning the previous one.
+    /// If there was none, `CygRoot::new()` is not called.
+    pub fn set_global(cygroot: CygRoot) -> Option<Arc<CygRoot>> {
+        install(Arc::new(cygroot))
+    }
+}
+
+/// Makes `cygroot` the global one, returning the previous one, if any.
+fn install(cygroot: Arc<CygRoot>) -> Option<Arc<CygRoot>> {
+    let mut cygroot = Some(cygroot);
+    let lock = GLOBAL.get_or_init(|| RwLock::new(cygroot.take().unwrap()));
+    let cygroot = cygroot?;
+    let mut global = lock.write().unwrap_or_else(PoisonError::into_inner);
+    Some(mem::replace(&mut *global, cygroot))
+}
+
+fn discover() -> CygRoot {
+    let mut cygroot = CygRoot::new();
+    cygroot.load_fstab();
+    cygroot
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use crate::CygRoot;
+
+#[test]
+fn installs_and_reloads_global() {
+    CygRoot::set_global(CygRoot::new());
+    let installed = CygRoot::global();
+    let from_thread = thread::spawn(CygRoot::global).join().unwrap();
+    assert!(Arc::ptr_eq(&installed, &from_thread));
+
+    let previous = CygRoot::set_global(CygRoot::new()).unwrap();
+    assert!(Arc::ptr_eq(&installed, &previous));
+    assert!(!Arc::ptr_eq(&installed, &CygRoot::global()));
+
+    CygRoot::set_global(CygRoot::from(PathBuf::from("F:\\cygwin"), true));
+    let reloaded = CygRoot::reload();
+    assert!(Arc::ptr_eq(&reloaded, &CygRoot::global()));
+    assert!(!Arc::ptr_eq(&installed, &reloaded));
+    let discovered = super::discover();
+    assert_eq!(reloaded.root_path(), discovered.root_path());
+    assert_eq!(reloaded.running_under_cygwin(), discovered.running_under_cygwin());
+    assert_eq!(reloaded.mounts(), discovered.mounts());
+}
+
+}
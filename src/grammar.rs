@@ -0,0 +1,285 @@
+//! The Cygwin path grammar, without looking at the file system:
+//! cygdrive prefixes, normalization and special characters.
+//!
+//! Only `core` and `alloc` are used, so that this module is there without the default `std` feature,
+//! as in a `no_std` loader.
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::str;
+
+/// Parses `/cygdrive/d/path`, where `prefix` is the cygdrive prefix (like `/cygdrive`, or `/` for MSYS2),
+/// returning the upper-case drive letter, and where `/path` starts.
+pub fn parse_cygdrive(path: &[u8], prefix: &str) -> Option<(char, usize)> {
+    let head = utf8_prefix(path);
+    let mut rest = Some(head);
+    for prefix_component in prefix.split('/').filter(|c| !c.is_empty()) {
+        rest = rest.and_then(|p| eat_chars(p, '/')).and_then(|p| eat_str(p, prefix_component));
+    }
+    let (drive_letter, after_drive) = rest.and_then(|p| eat_chars(p, '/')).and_then(pop_char)?;
+    let cygdrive_end = head.len() - after_drive.len();
+    if !valid_drive_letter(drive_letter) {
+        return None;
+    }
+    match path.get(cygdrive_end) {
+        None | Some(&b'/') => Some((ascii_upcase(drive_letter), cygdrive_end)),
+        Some(_) => None,
+    }
+}
+
+/// Returns the names of a path, split at slashes and backslashes, skipping empty ones.
+pub fn components(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    path.split(|&b| b == b'/' || b == b'\\').filter(|component| !component.is_empty())
+}
+
+/// Removes `.`, `..` and repeated slashes from an absolute Cygwin path, in UTF-8 (or WTF-8).
+/// `/..` is `/`, as in Cygwin.
+pub fn normalize_posix_path(path: &[u8]) -> Vec<u8> {
+    let mut components = Vec::<&[u8]>::new();
+    for component in path.split(|&b| b == b'/' || b == b'\\') {
+        match component {
+            b"" | b"." => {},
+            b".." => { components.pop(); },
+            _ => components.push(component),
+        }
+    }
+    let mut ret = Vec::<u8>::with_capacity(path.len());
+    for component in components.iter() {
+        ret.push(b'/');
+        ret.extend_from_slice(component);
+    }
+    if ret.is_empty() {
+        ret.push(b'/');
+    }
+    ret
+}
+
+/// Whether `path` is `dir` or under it, where both use slashes (or backslashes in `path`).
+pub fn path_is_under(path: &[u8], dir: &[u8]) -> bool {
+    if !path.starts_with(dir) {
+        return false;
+    }
+    match path.get(dir.len()) {
+        None | Some(&b'/') | Some(&b'\\') => true,
+        Some(_) => false,
+    }
+}
+
+fn is_special_char(ch: char) -> bool {
+    matches!(ch, '\u{1}'..='\u{1f}' | '"' | '*' | ':' | '<' | '>' | '?' | '|')
+}
+
+/// Replaces characters not allowed in a Windows name with U+F000 + character, as Cygwin does.
+/// Trailing dots and spaces, which Windows would strip, are replaced too (unless the name is `.` or `..`).
+/// `name` is a single path component, in UTF-8 (or WTF-8, for names with unpaired surrogates).
+pub fn map_special_chars(name: &[u8]) -> Cow<'_, [u8]> {
+    let trailing_start = if name == b"." || name == b".." {
+        name.len()
+    } else {
+        name.iter().rposition(|&b| b != b'.' && b != b' ').map_or(0, |i| i + 1)
+    };
+    if trailing_start == name.len() && !name.iter().any(|&b| is_special_byte(b)) {
+        return Cow::Borrowed(name);
+    }
+    let mut ret = Vec::<u8>::with_capacity(name.len() + 16);
+    for (i, &b) in name.iter().enumerate() {
+        if is_special_byte(b) || i >= trailing_start {
+            // U+F000 + b in UTF-8, for b below 0x80
+            ret.push(0xef);
+            ret.push(0x80 | (b >> 6));
+            ret.push(0x80 | (b & 0x3f));
+        } else {
+            ret.push(b);
+        }
+    }
+    Cow::Owned(ret)
+}
+
+/// Turns U+F000 + character back into the character, for characters `map_special_chars` replaces.
+pub fn unmap_special_chars(name: &[u8]) -> Cow<'_, [u8]> {
+    let unmapped_at = |i: usize| {
+        if i + 3 <= name.len() && name[i] == 0xef && (name[i + 1] == 0x80 || name[i + 1] == 0x81)
+                && (name[i + 2] & 0xc0) == 0x80 {
+            let orig = (name[i + 1] & 0x3f) << 6 | (name[i + 2] & 0x3f);
+            if is_special_byte(orig) || orig == b'.' || orig == b' ' {
+                return Some(orig);
+            }
+        }
+        None
+    };
+    if !(0..name.len()).any(|i| unmapped_at(i).is_some()) {
+        return Cow::Borrowed(name);
+    }
+    let mut ret = Vec::<u8>::with_capacity(name.len());
+    let mut i = 0;
+    while i < name.len() {
+        match unmapped_at(i) {
+            Some(orig) => {
+                ret.push(orig);
+                i += 3;
+            },
+            None => {
+                ret.push(name[i]);
+                i += 1;
+            },
+        }
+    }
+    Cow::Owned(ret)
+}
+
+fn is_special_byte(b: u8) -> bool {
+    b < 0x80 && is_special_char(b as char)
+}
+
+/// Whether `x` could be a drive letter.
+pub fn valid_drive_letter(x: char) -> bool {
+    x.is_ascii_alphabetic()
+}
+
+/// Splits the first character off `s`.
+pub fn pop_char(s: &str) -> Option<(char, &str)> {
+    let mut ret_beg = 0;
+    let mut ret_ch = None;
+    for (i, ch) in s.char_indices() {
+        if i == 0 {
+            ret_ch = Some(ch);
+        } else {
+            ret_beg = i;
+            break;
+        }
+    }
+    match ret_ch {
+        None => None,
+        Some(ch) => {
+            if ret_beg == 0 {
+                Some((ch, ""))
+            } else {
+                let ret_b = &s.as_bytes()[ret_beg..];
+                let ret_s = unsafe {
+                    str::from_utf8_unchecked(ret_b)
+                };
+                Some((ch, ret_s))
+            }
+        }
+    }
+}
+
+/// Skips one or more `x` at the start of `s`, returning the rest.
+pub fn eat_chars(s: &str, x: char) -> Option<&str> {
+    let mut ret_beg = s.len();
+    for (i, ch) in s.char_indices() {
+        if ch != x {
+            ret_beg = i;
+            break;
+        }
+    }
+    if ret_beg == 0 {
+        None
+    } else {
+        let ret_b = &s.as_bytes()[ret_beg..];
+        let ret_s = unsafe {
+            str::from_utf8_unchecked(ret_b)
+        };
+        Some(ret_s)
+    }
+}
+
+/// Skips `s1` at the start of `s`, returning the rest.
+pub fn eat_str<'a>(s: &'a str, s1: &str) -> Option<&'a str> {
+    let mut chs1 = s1.chars();
+    let mut ret_beg = s.len();
+    for (i, ch) in s.char_indices() {
+        match chs1.next() {
+            None => {
+                ret_beg = i;
+                break;
+            },
+            Some(x) => {
+                if ch != x {
+                    return None;
+                }
+            },
+        }
+    }
+    if chs1.next().is_some() {
+        None
+    } else {
+        let ret_b = &s.as_bytes()[ret_beg..];
+        let ret_s = unsafe {
+            str::from_utf8_unchecked(ret_b)
+        };
+        Some(ret_s)
+    }
+}
+
+/// Upper-cases ASCII letters, leaving other characters as they are.
+pub fn ascii_upcase(x: char) -> char {
+    x.to_ascii_uppercase()
+}
+
+/// Returns the longest prefix of `data` that is valid UTF-8.
+pub fn utf8_prefix(data: &[u8]) -> &str {
+    match str::from_utf8(data) {
+        Ok(s) => s,
+        Err(err) => str::from_utf8(&data[..err.valid_up_to()]).unwrap_or(""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+use alloc::vec::Vec;
+
+use super::*;
+
+#[test]
+fn parses_cygdrive() {
+    assert_eq!(parse_cygdrive(b"/cygdrive/c/x", "/cygdrive"), Some(('C', 11)));
+    assert_eq!(parse_cygdrive(b"//cygdrive//d", "/cygdrive/"), Some(('D', 13)));
+    assert_eq!(parse_cygdrive(b"/cygdrive/cd", "/cygdrive"), None);
+    assert_eq!(parse_cygdrive(b"/cygdrive", "/cygdrive"), None);
+    assert_eq!(parse_cygdrive(b"/mnt/e/x", "/mnt"), Some(('E', 6)));
+    assert_eq!(parse_cygdrive(b"/c/x", "/"), Some(('C', 2)));
+    assert_eq!(parse_cygdrive(b"/usr/x", "/"), None);
+    assert_eq!(parse_cygdrive(b"/cygdrive/c/\xff", "/cygdrive"), Some(('C', 11)));
+}
+
+#[test]
+fn splits_and_normalizes() {
+    assert_eq!(components(b"/a//b\\c/").collect::<Vec<_>>(), [&b"a"[..], b"b", b"c"]);
+    assert_eq!(normalize_posix_path(b"/home//user/./src/"), b"/home/user/src");
+    assert_eq!(normalize_posix_path(b"/.."), b"/");
+    assert!(path_is_under(b"/usr/bin/x", b"/usr/bin"));
+    assert!(!path_is_under(b"/usr/binx", b"/usr/bin"));
+}
+
+#[test]
+fn maps_special_chars() {
+    assert_eq!(map_special_chars(b"plain name"), &b"plain name"[..]);
+    assert_eq!(map_special_chars(b"a:b?"), "a\u{f03a}b\u{f03f}".as_bytes());
+    assert_eq!(map_special_chars(b"dots.. "), "dots\u{f02e}\u{f02e}\u{f020}".as_bytes());
+    assert_eq!(map_special_chars(b".."), &b".."[..]);
+    assert_eq!(map_special_chars(b"\x01\x7f"), "\u{f001}\x7f".as_bytes());
+    assert_eq!(unmap_special_chars("a\u{f03a}b\u{f03f}".as_bytes()), &b"a:b?"[..]);
+    assert_eq!(unmap_special_chars("dots\u{f02e}".as_bytes()), &b"dots."[..]);
+    assert_eq!(unmap_special_chars("\u{f041}".as_bytes()), "\u{f041}".as_bytes());
+    assert_eq!(unmap_special_chars(&[0xed, 0xa0, 0x80, 0xef, 0x80, 0xbf]), &[0xed, 0xa0, 0x80, b'?'][..]);
+}
+
+#[test]
+fn eats_prefixes() {
+    assert_eq!(eat_chars("//a", '/'), Some("a"));
+    assert_eq!(eat_chars("//", '/'), Some(""));
+    assert_eq!(eat_chars("a", '/'), None);
+    assert_eq!(eat_str("cygdrive/c", "cygdrive"), Some("/c"));
+    assert_eq!(eat_str("cygdrive", "cygdrive"), Some(""));
+    assert_eq!(eat_str("cyg", "cygdrive"), None);
+    assert_eq!(eat_str("abc", ""), Some("abc"));
+    assert_eq!(pop_char("ÿx"), Some(('ÿ', "x")));
+    assert_eq!(pop_char(""), None);
+    assert_eq!(ascii_upcase('d'), 'D');
+    assert_eq!(ascii_upcase('ÿ'), 'ÿ');
+    assert_eq!(utf8_prefix(b"ab\xffc"), "ab");
+}
+
+}
@@ -0,0 +1,261 @@
+//! Parsing of the Cygwin files that define home directories.
+
+#[cfg(any(windows, test))]
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::env::EnvSource;
+
+/// Finds the home directory of `user` in the contents of `/etc/passwd`.
+/// Lines are `name:password:uid:gid:gecos:home:shell`; the first matching one wins, as in Cygwin.
+#[cfg(any(windows, test))]
+pub fn passwd_home_dir<'a>(passwd: &'a str, user: &str) -> Option<&'a str> {
+    for line in passwd.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with("#") {
+            continue;
+        }
+        let mut fields = line.split(':');
+        if fields.next() != Some(user) {
+            continue;
+        }
+        return match fields.nth(4) {
+            Some(home) if !home.is_empty() => Some(home),
+            _ => None,
+        };
+    }
+    None
+}
+
+/// Splits `~user/rest` into `("user", "/rest")`, and `~` or `~/rest` into `("", ...)`.
+/// Returns `None` for paths not starting with a tilde.
+#[cfg(any(windows, test))]
+pub fn split_tilde_prefix(path: &str) -> Option<(&str, &str)> {
+    if !path.starts_with("~") {
+        return None;
+    }
+    let after_tilde = &path[1..];
+    let user_end = after_tilde.find(&['/', '\\'][..]).unwrap_or(after_tilde.len());
+    Some((&after_tilde[..user_end], &after_tilde[user_end..]))
+}
+
+/// The account information Cygwin evaluates `db_home` schemes against.
+/// `UserContext::from_env()` fills in the current user; other fields could come from the directory service.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct UserContext {
+    /// The Cygwin user name, substituted for `%u`. Usually the same as `windows_user`.
+    pub cygwin_user: String,
+    /// The Windows user name, substituted for `%U`.
+    pub windows_user: String,
+    /// The Windows domain (or machine) name, substituted for `%D`.
+    pub domain: String,
+    /// The Windows home directory (like `C:\Users\user`), used by `windows` and `%H`.
+    pub windows_home: Option<PathBuf>,
+    /// The `cygwinHome` directory service attribute, used by `cygwin`.
+    pub cygwin_home: Option<String>,
+    /// The `unixHomeDirectory` directory service attribute, used by `unix`.
+    pub unix_home: Option<String>,
+    /// The account description, which could contain `<cygwin home="/path"/>`, used by `desc`.
+    pub description: Option<String>,
+}
+
+impl UserContext {
+    /// Describes the current user, from `USERNAME`, `USERDOMAIN` and `USERPROFILE` of `env`.
+    pub fn from_env(env: &dyn EnvSource) -> UserContext {
+        let var = |name: &str| env.var_os(name).map(|s| s.to_string_lossy().into_owned());
+        let user = var("USERNAME").unwrap_or_default();
+        UserContext {
+            cygwin_user: user.clone(),
+            windows_user: user,
+            domain: var("USERDOMAIN").unwrap_or_default(),
+            windows_home: env.var_os("USERPROFILE").map(PathBuf::from),
+            cygwin_home: None,
+            unix_home: None,
+            description: None,
+        }
+    }
+}
+
+/// The `db_home` schemes Cygwin uses when `/etc/nsswitch.conf` does not set any.
+#[cfg(any(windows, test))]
+const DEFAULT_DB_HOME: &[&str] = &["cygwin", "desc"];
+
+/// The home directory Cygwin uses when no `db_home` scheme gives one.
+#[cfg(any(windows, test))]
+const FALLBACK_HOME: &str = "/home/%U";
+
+/// Returns the schemes of the last `db_home:` line in the contents of `/etc/nsswitch.conf`.
+#[cfg(any(windows, test))]
+pub fn nsswitch_db_home(nsswitch: &str) -> Option<Vec<&str>> {
+    let mut ret = None;
+    for line in nsswitch.lines() {
+        let line = match line.find('#') {
+            Some(comment_beg) => &line[..comment_beg],
+            None => line,
+        };
+        let line = line.trim();
+        if !line.starts_with("db_home:") {
+            continue;
+        }
+        let schemes: Vec<&str> = line["db_home:".len()..].split_whitespace().collect();
+        if !schemes.is_empty() {
+            ret = Some(schemes);
+        }
+    }
+    ret
+}
+
+/// Evaluates the `db_home` setting of `/etc/nsswitch.conf` (given its contents, if any) for `user`,
+/// with `to_posix` converting Windows paths to Cygwin ones.
+/// Schemes are tried in order, `cygwin desc` if none are set; if none gives a directory,
+/// `/home/%U` is used, as in Cygwin.
+#[cfg(any(windows, test))]
+pub fn db_home_dir(nsswitch: Option<&str>, user: &UserContext, to_posix: &dyn Fn(&Path) -> String) -> Option<String> {
+    let schemes = nsswitch.and_then(nsswitch_db_home).unwrap_or_else(|| DEFAULT_DB_HOME.to_vec());
+    for scheme in schemes.iter() {
+        let home = match *scheme {
+            "windows" => user.windows_home.as_ref().map(|p| to_posix(p.as_path())),
+            "cygwin" => user.cygwin_home.clone(),
+            "unix" => user.unix_home.clone(),
+            "desc" => user.description.as_ref().and_then(|d| description_home_dir(d)).map(String::from),
+            _ => expand_home_placeholders(scheme, user, to_posix),
+        };
+        match home {
+            Some(ref home) if !home.is_empty() => return Some(home.clone()),
+            _ => {},
+        }
+    }
+    expand_home_placeholders(FALLBACK_HOME, user, to_posix)
+}
+
+/// Substitutes `%u`, `%U`, `%D`, `%H`, `%_` and `%%` in a `db_home` path scheme.
+/// Returns `None` for schemes that are neither known keywords nor paths, and if `%H` is used without a Windows home.
+#[cfg(any(windows, test))]
+fn expand_home_placeholders(scheme: &str, user: &UserContext, to_posix: &dyn Fn(&Path) -> String) -> Option<String> {
+    if !scheme.starts_with('/') && !scheme.starts_with('%') {
+        return None;
+    }
+    let mut ret = String::with_capacity(scheme.len() + user.windows_user.len());
+    let mut chars = scheme.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            ret.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => ret.push_str(&user.cygwin_user),
+            Some('U') => ret.push_str(&user.windows_user),
+            Some('D') => ret.push_str(&user.domain),
+            Some('H') => match user.windows_home {
+                None => return None,
+                Some(ref home) => ret.push_str(&to_posix(home.as_path())),
+            },
+            Some('_') => ret.push(' '),
+            Some('%') => ret.push('%'),
+            Some(other) => { ret.push('%'); ret.push(other); },
+            None => ret.push('%'),
+        }
+    }
+    Some(ret)
+}
+
+/// Extracts `/path` from `... <cygwin home="/path"/> ...` in an account description.
+#[cfg(any(windows, test))]
+fn description_home_dir(description: &str) -> Option<&str> {
+    let cygwin_elem = &description[description.find("<cygwin ")?..];
+    let cygwin_elem = &cygwin_elem[..cygwin_elem.find("/>")?];
+    let home_attr = "home=\"";
+    let home = &cygwin_elem[cygwin_elem.find(home_attr)? + home_attr.len()..];
+    home.find('"').map(|home_end| &home[..home_end])
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+
+const PASSWD: &str = "\
+root:*:0:0:root:/root:/bin/bash\r
+# comment:x:1:1::/nowhere:
+alice:*:197609:197121:U-HOST\\alice,S-1-5-21:/home/alice:/bin/bash
+bob:*:197610:197121:::/bin/bash
+";
+
+#[test]
+fn finds_home_in_passwd() {
+    assert_eq!(passwd_home_dir(PASSWD, "alice"), Some("/home/alice"));
+    assert_eq!(passwd_home_dir(PASSWD, "root"), Some("/root"));
+}
+
+#[test]
+fn ignores_missing_users_and_empty_homes() {
+    assert_eq!(passwd_home_dir(PASSWD, "bob"), None);
+    assert_eq!(passwd_home_dir(PASSWD, "carol"), None);
+    assert_eq!(passwd_home_dir(PASSWD, "# comment"), None);
+}
+
+#[test]
+fn splits_tilde_prefix() {
+    assert_eq!(split_tilde_prefix("~"), Some(("", "")));
+    assert_eq!(split_tilde_prefix("~/builds/out"), Some(("", "/builds/out")));
+    assert_eq!(split_tilde_prefix("~alice/x"), Some(("alice", "/x")));
+    assert_eq!(split_tilde_prefix("/home/~alice"), None);
+}
+
+#[test]
+fn reads_last_db_home_line() {
+    let conf = "# db_home: windows\npasswd: files db\ndb_home: cygwin desc\ndb_home:  unix  /home/%U # trailing\n";
+    assert_eq!(nsswitch_db_home(conf), Some(vec!["unix", "/home/%U"]));
+    assert_eq!(nsswitch_db_home("passwd: files\n"), None);
+}
+
+fn alice() -> UserContext {
+    UserContext {
+        cygwin_user: String::from("alice"),
+        windows_user: String::from("Alice"),
+        domain: String::from("CORP"),
+        windows_home: Some(PathBuf::from("C:/Users/Alice")),
+        cygwin_home: None,
+        unix_home: Some(String::from("/u/alice")),
+        description: Some(String::from("Staff <cygwin home=\"/d/alice\" shell=\"/bin/zsh\"/>")),
+    }
+}
+
+fn to_posix(p: &Path) -> String {
+    format!("/cygdrive/c{}", &p.to_string_lossy()[2..])
+}
+
+#[test]
+fn evaluates_db_home_schemes_in_order() {
+    let user = alice();
+    assert_eq!(db_home_dir(Some("db_home: cygwin desc unix"), &user, &to_posix), Some(String::from("/d/alice")));
+    assert_eq!(db_home_dir(Some("db_home: cygwin unix desc"), &user, &to_posix), Some(String::from("/u/alice")));
+    assert_eq!(db_home_dir(Some("db_home: windows"), &user, &to_posix), Some(String::from("/cygdrive/c/Users/Alice")));
+}
+
+#[test]
+fn falls_back_to_home_of_windows_user() {
+    let mut user = alice();
+    assert_eq!(db_home_dir(Some("db_home: cygwin"), &user, &to_posix), Some(String::from("/home/Alice")));
+    user.description = None;
+    assert_eq!(db_home_dir(None, &user, &to_posix), Some(String::from("/home/Alice")));
+}
+
+#[test]
+fn defaults_to_cygwin_desc() {
+    let mut user = alice();
+    assert_eq!(db_home_dir(None, &user, &to_posix), Some(String::from("/d/alice")));
+    assert_eq!(db_home_dir(Some("passwd: files db"), &user, &to_posix), Some(String::from("/d/alice")));
+    user.cygwin_home = Some(String::from("/c/alice"));
+    assert_eq!(db_home_dir(None, &user, &to_posix), Some(String::from("/c/alice")));
+}
+
+#[test]
+fn expands_placeholders() {
+    let user = alice();
+    let conf = "db_home: /home/%D/%u%_%U%%";
+    assert_eq!(db_home_dir(Some(conf), &user, &to_posix), Some(String::from("/home/CORP/alice Alice%")));
+    assert_eq!(db_home_dir(Some("db_home: %H/cyg"), &user, &to_posix), Some(String::from("/cygdrive/c/Users/Alice/cyg")));
+}
+
+}
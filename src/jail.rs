@@ -0,0 +1,160 @@
+//! Checks for `CygRoot::resolve_path_within()`, which keeps resolution inside a base directory.
+
+use std::error::Error;
+#[cfg(any(windows, test))]
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Which symlink targets `CygRoot::resolve_path_within()` follows at all, as set with `CygRoot::set_jail_policy()`.
+/// Targets allowed here still have to stay within the base directory.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+pub struct JailPolicy {
+    /// Whether symlinks may point to drives, as `/cygdrive/d/path` or `D:\path`.
+    pub allow_cygdrive: bool,
+    /// Whether symlinks may point to network shares, as `//server/share/path`.
+    pub allow_unc: bool,
+}
+
+/// Why `CygRoot::resolve_path_within()` refused a path.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum JailError {
+    /// The path, or a symlink hop on the way (given), leads outside of the base directory.
+    Escapes(PathBuf),
+    /// The symlink (given) points to a drive, which the policy denies.
+    CygdriveTarget(PathBuf),
+    /// The symlink (given) points to a network share, which the policy denies.
+    UncTarget(PathBuf),
+    /// The symlink (given) could not be resolved in a bounded number of hops, as in a cycle.
+    TooManyLinks(PathBuf),
+    /// The native symlink or junction (given), which Windows would follow, could not be read.
+    UnreadableLink(PathBuf),
+}
+
+impl fmt::Display for JailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JailError::Escapes(ref path) => write!(f, "{} is outside of the base directory", path.display()),
+            JailError::CygdriveTarget(ref path) => write!(f, "symlink {} points to a drive", path.display()),
+            JailError::UncTarget(ref path) => write!(f, "symlink {} points to a network share", path.display()),
+            JailError::TooManyLinks(ref path) => write!(f, "too many levels of symlinks at {}", path.display()),
+            JailError::UnreadableLink(ref path) => write!(f, "could not read the target of link {}", path.display()),
+        }
+    }
+}
+
+impl Error for JailError {}
+
+/// Applies `.` and `..` to a native path without looking at the file system, as Windows does.
+/// `..` above the root (or the start of a relative path) is dropped.
+pub fn normalize_native_path(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+    let mut normal_depth = 0;
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if normal_depth > 0 {
+                    ret.pop();
+                    normal_depth -= 1;
+                }
+            },
+            Component::Normal(name) => {
+                ret.push(name);
+                normal_depth += 1;
+            },
+            Component::Prefix(_) | Component::RootDir => ret.push(component.as_os_str()),
+        }
+    }
+    ret
+}
+
+/// Whether normalized `path` is `base` or under it, comparing names ASCII case-insensitively, as Windows does.
+pub fn is_within(base: &Path, path: &Path) -> bool {
+    let mut path_components = path.components();
+    for base_component in base.components() {
+        match path_components.next() {
+            Some(path_component) => {
+                let base_b = base_component.as_os_str().as_encoded_bytes();
+                let path_b = path_component.as_os_str().as_encoded_bytes();
+                if !base_b.eq_ignore_ascii_case(path_b) {
+                    return false;
+                }
+            },
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Turns the target `fs::read_link()` returns for a native symlink or junction into a plain path,
+/// as `\\?\C:\Windows` into `C:\Windows`, and `\\?\UNC\server\share` into `\\server\share`.
+#[cfg(any(windows, test))]
+pub fn strip_verbatim_prefix(target: &Path) -> PathBuf {
+    let target_b = target.as_os_str().as_encoded_bytes();
+    let stripped = if target_b.starts_with(b"\\\\?\\UNC\\") {
+        let mut unc = b"\\\\".to_vec();
+        unc.extend_from_slice(&target_b[8..]);
+        unc
+    } else if target_b.starts_with(b"\\\\?\\") {
+        target_b[4..].to_vec()
+    } else {
+        return PathBuf::from(target);
+    };
+    // Split after ASCII characters, so still in the encoding of `OsStr`
+    PathBuf::from(unsafe { OsString::from_encoded_bytes_unchecked(stripped) })
+}
+
+/// Whether a symlink target is a UNC path, like `//server/share`.
+#[cfg(any(windows, test))]
+pub fn is_unc_target(target: &[u8]) -> bool {
+    target.len() > 2 && (target[0] == b'/' || target[0] == b'\\') && (target[1] == b'/' || target[1] == b'\\')
+        && target[2] != b'/' && target[2] != b'\\'
+}
+
+/// Whether a symlink target is a Windows drive path, like `D:\path` or `D:/path`.
+#[cfg(any(windows, test))]
+pub fn is_drive_target(target: &[u8]) -> bool {
+    target.len() >= 2 && target[1] == b':' && target[0].is_ascii_alphabetic()
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::path::{Path, PathBuf};
+
+use super::{normalize_native_path, is_within, is_unc_target, is_drive_target, strip_verbatim_prefix};
+
+#[test]
+fn normalizes_native_paths() {
+    assert_eq!(normalize_native_path(Path::new("/a/b/../c/./d")), PathBuf::from("/a/c/d"));
+    assert_eq!(normalize_native_path(Path::new("/a/../../..")), PathBuf::from("/"));
+    assert_eq!(normalize_native_path(Path::new("a/../../b")), PathBuf::from("b"));
+}
+
+#[test]
+fn checks_containment_by_components() {
+    assert!(is_within(Path::new("/ws/build"), Path::new("/ws/build")));
+    assert!(is_within(Path::new("/ws/build"), Path::new("/WS/Build/x")));
+    assert!(!is_within(Path::new("/ws/build"), Path::new("/ws/builds")));
+    assert!(!is_within(Path::new("/ws/build"), Path::new("/ws")));
+}
+
+#[test]
+fn strips_verbatim_prefixes() {
+    assert_eq!(strip_verbatim_prefix(Path::new("\\\\?\\C:\\Windows")), PathBuf::from("C:\\Windows"));
+    assert_eq!(strip_verbatim_prefix(Path::new("\\\\?\\UNC\\server\\share")), PathBuf::from("\\\\server\\share"));
+    assert_eq!(strip_verbatim_prefix(Path::new("..\\x")), PathBuf::from("..\\x"));
+}
+
+#[test]
+fn classifies_targets() {
+    assert!(is_unc_target(b"//server/share"));
+    assert!(is_unc_target(b"\\\\server\\share"));
+    assert!(!is_unc_target(b"/tmp"));
+    assert!(!is_unc_target(b"///tmp"));
+    assert!(is_drive_target(b"C:/Windows"));
+    assert!(!is_drive_target(b"C/Windows"));
+}
+
+}
@@ -8,11 +8,13 @@ This is wrong, and gives all the bugs.
 
 For bugs description, see the documentation for `join_symlink_native_path_and_cygwin_target` below.
 The deprecated Windows Explorer Shortcut symlinks are not interpreted.
-Mount points are not recognized.
+Mount points from `/etc/fstab` are only recognized after `CygRoot::load_fstab()`,
+otherwise only the default `/usr/bin` and `/usr/lib` ones are.
 
 ```rust
 extern crate cygwin_fs;
 
+# #[cfg(feature = "std")]
 fn main() {
     let p = ::std::path::PathBuf::from("/cygwin/path");
 
@@ -21,10 +23,12 @@ fn main() {
 
     println!("{:?} => {:?}", p, w);
 }
+# #[cfg(not(feature = "std"))]
+# fn main() {}
 ```
 
 If file access time matters:
-```rust,no_run
+```rust,ignore
 if cfg!(windows) {
     if cygwin_fs::maybe_cygwin_symlink(p) {
         ...
@@ -32,42 +36,152 @@ if cfg!(windows) {
 }
 ```
 
+The path grammar (cygdrive prefixes, normalization, special characters) is also available
+without `std`, in the `grammar` module, with `default-features = false`.
+
 */
 
-#[cfg(windows)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate encoding_rs;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(all(windows, feature = "std"))]
 extern crate kernel32;
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 extern crate winapi;
 
+pub mod grammar;
+
+#[cfg(feature = "tokio")]
+mod async_fs;
+#[cfg(feature = "std")]
+mod batch;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+mod charset;
+#[cfg(feature = "std")]
+mod codepage;
+#[cfg(feature = "std")]
+mod compat;
+#[cfg(feature = "std")]
+mod decode;
+#[cfg(feature = "std")]
+mod discovery;
+#[cfg(feature = "std")]
+mod env;
+#[cfg(feature = "std")]
+mod folders;
+#[cfg(feature = "std")]
+mod fstab;
+#[cfg(feature = "std")]
+mod global;
+#[cfg(feature = "std")]
+mod home;
+#[cfg(feature = "std")]
+mod jail;
+#[cfg(feature = "std")]
+mod pe;
+#[cfg(feature = "std")]
+mod policy;
+#[cfg(feature = "std")]
+mod trace;
+
+#[cfg(feature = "std")]
+pub use builder::{CygRootBuilder, BuildError};
+#[cfg(feature = "std")]
+pub use cache::CachingResolver;
+#[cfg(feature = "std")]
+pub use charset::Charset;
+#[cfg(feature = "std")]
+pub use codepage::Codepage;
+#[cfg(feature = "std")]
+pub use compat::{CompatProfile, SymlinkFormat};
+#[cfg(feature = "std")]
+pub use decode::{DecodeError, decode_symlink_target, MAX_SYMLINK_SIZE};
+#[cfg(feature = "std")]
+pub use discovery::{RootStrategy, DiscoveryOutcome, DiscoveryAttempt, RootValidation};
+#[cfg(feature = "std")]
+pub use env::{EnvSource, ProcessEnv, EnvBlock};
+#[cfg(feature = "std")]
+pub use folders::{SpecialFolder, PathStyle};
+#[cfg(feature = "std")]
+pub use fstab::{FstabEntry, parse_fstab};
+#[cfg(feature = "std")]
+pub use home::UserContext;
+#[cfg(feature = "std")]
+pub use jail::{JailPolicy, JailError};
+#[cfg(feature = "std")]
+pub use pe::{PeError, Machine, FileVersion, RuntimeInfo, read_runtime_info, parse_runtime_info};
+#[cfg(feature = "std")]
+pub use pe::{Flavor, Subsystem, ExecutableInfo, is_cygwin_executable, read_executable_info, parse_executable_info};
+#[cfg(feature = "std")]
+pub use policy::{SymlinkPolicy, DEFAULT_MAX_SYMLINK_HOPS};
+#[cfg(feature = "std")]
+pub use trace::ResolutionStep;
+#[cfg(feature = "tokio")]
+pub use async_fs::maybe_cygwin_symlink_async;
+
+#[cfg(all(windows, feature = "std"))]
+use trace::Trace;
+#[cfg(all(windows, feature = "std"))]
+use cache::SymlinkProbe;
+#[cfg(all(windows, feature = "std"))]
+use grammar::{path_is_under, valid_drive_letter};
+
+#[cfg(feature = "std")]
+use std::ffi::{OsStr, OsString};
+#[cfg(feature = "std")]
 use std::path::{Path,PathBuf};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(all(windows, feature = "std"))]
+use std::path::Component;
 
-#[cfg(windows)]
-use std::ffi::{OsString};
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 use std::os::windows::ffi::OsStrExt;
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 use std::iter::once;
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
+use std::mem;
+#[cfg(all(windows, feature = "std"))]
 use std::vec::Vec;
-#[cfg(windows)]
-use std::fs::File;
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
+use std::fs::{self, File};
+#[cfg(all(windows, feature = "std"))]
 use std::io::Read;
+#[cfg(all(windows, feature = "std"))]
+use std::ptr::null_mut;
 
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 use winapi::winnt::{
     FILE_ATTRIBUTE_SYSTEM,
+    FILE_ATTRIBUTE_REPARSE_POINT,
+    FILE_SHARE_READ,
+    FILE_SHARE_WRITE,
+    FILE_SHARE_DELETE,
 };
 
 // Stub
 
-#[cfg(not(windows))]
+#[cfg(all(not(windows), feature = "std"))]
 #[derive(Clone,Debug)]
 pub struct CygRoot {
     empty_root_pathbuf: PathBuf,
 }
 
-#[cfg(not(windows))]
+#[cfg(all(not(windows), feature = "std"))]
 impl CygRoot {
     pub fn new() -> CygRoot {
         CygRoot {
@@ -79,60 +193,153 @@ impl CygRoot {
             empty_root_pathbuf: PathBuf::new(),
         }
     }
+    pub fn from_env(_env: &dyn EnvSource) -> CygRoot { CygRoot::new() }
+    pub fn discover(_strategies: &[RootStrategy]) -> (CygRoot, Vec<DiscoveryAttempt>) { (CygRoot::new(), Vec::new()) }
+    pub fn discover_in_env(_strategies: &[RootStrategy], _env: &dyn EnvSource) -> (CygRoot, Vec<DiscoveryAttempt>) {
+        (CygRoot::new(), Vec::new())
+    }
 
     pub fn root_path(&self) -> &Path { self.empty_root_pathbuf.as_path() }
     pub fn running_under_cygwin(&self) -> bool { false }
+    pub fn cygwin_dll_path(&self) -> Option<&Path> { None }
+    pub fn validate(&self) -> RootValidation { discovery::validate_root(self.root_path(), None) }
+    pub fn runtime_info(&self) -> Option<RuntimeInfo> { None }
+    pub fn compat_profile(&self) -> CompatProfile { CompatProfile::default() }
+    pub fn set_compat_profile(&mut self, _profile: Option<CompatProfile>) {}
+    pub fn symlink_codepage(&self) -> Codepage { Codepage::Utf8 }
+    pub fn set_symlink_codepage(&mut self, _codepage: Codepage) {}
+    pub fn charset(&self) -> Charset { Charset::utf8() }
+    pub fn set_charset(&mut self, _charset: Option<Charset>) {}
+    pub fn jail_policy(&self) -> JailPolicy { JailPolicy::default() }
+    pub fn set_jail_policy(&mut self, _policy: JailPolicy) {}
+    pub fn symlink_policy(&self) -> SymlinkPolicy { SymlinkPolicy::default() }
+    pub fn set_symlink_policy(&mut self, _policy: SymlinkPolicy) {}
+    pub fn mounts(&self) -> &[(String, PathBuf)] { &[] }
+    pub fn add_mount(&mut self, _mount_point: &str, _native: PathBuf) {}
+    pub fn load_fstab(&mut self) -> bool { false }
+    pub fn cygdrive_prefix(&self) -> &str { "/cygdrive" }
+    pub fn set_cygdrive_prefix(&mut self, _prefix: Option<String>) {}
+    pub fn max_symlink_hops(&self) -> usize { DEFAULT_MAX_SYMLINK_HOPS }
+    pub fn set_max_symlink_hops(&mut self, _hops: usize) {}
+    pub fn output_style(&self) -> PathStyle { PathStyle::Windows }
+    pub fn set_output_style(&mut self, _style: PathStyle) {}
+    pub fn flavor(&self) -> Flavor { Flavor::Cygwin }
+    pub fn set_flavor(&mut self, _flavor: Flavor) {}
+    pub fn cwd(&self) -> Option<&str> { None }
+    pub fn set_cwd(&mut self, _cwd: Option<&str>) {}
+    pub fn set_cwd_from_pwd(&mut self) {}
+    pub fn home_dir(&self, _user: Option<&str>) -> Option<String> { None }
+    pub fn expand_tilde(&self, _path: &str) -> Option<String> { None }
+    pub fn expand_tilde_to_native(&self, _path: &str) -> Option<PathBuf> { None }
+    pub fn db_home_dir(&self, _user: &UserContext) -> Option<String> { None }
+    pub fn convert_path_to_cygwin(&self, _path: &Path) -> String { String::new() }
+    pub fn convert_path_to_cygwin_os(&self, _path: &Path) -> OsString { OsString::new() }
+    pub fn convert_path_to_cygwin_into(&self, _path: &Path, out: &mut String) { out.clear() }
+    pub fn convert_path_to_cygwin_cow<'a>(&self, _path: &'a Path) -> Cow<'a, OsStr> { Cow::Owned(OsString::new()) }
+    pub fn convert_path_to_cygwin_bytes(&self, _path: &Path) -> Vec<u8> { Vec::new() }
+    pub fn special_folder(&self, _folder: SpecialFolder, _style: PathStyle) -> Option<PathBuf> { None }
+    pub fn special_folder_in_env(&self, _folder: SpecialFolder, _style: PathStyle,
+            _env: &dyn EnvSource) -> Option<PathBuf> { None }
     pub fn convert_path_to_native(&self, _path: &str) -> PathBuf { PathBuf::new() }
+    pub fn convert_path_to_native_os<P: AsRef<OsStr>>(&self, _path: P) -> PathBuf { PathBuf::new() }
+    pub fn convert_path_to_native_into<P: AsRef<OsStr>>(&self, _path: P, out: &mut OsString) { out.clear() }
+    pub fn convert_bytes_to_native(&self, _path: &[u8]) -> PathBuf { PathBuf::new() }
     pub fn read_symlink_contents(&self, _path: &Path) -> Option<PathBuf> { None }
+    pub fn try_read_symlink_contents(&self, _path: &Path) -> Result<Option<PathBuf>, DecodeError> { Ok(None) }
     pub fn resolve_symlink_once(&self, _path: &Path) -> PathBuf { PathBuf::new() }
     pub fn resolve_symlink(&self, _path: &Path) -> PathBuf { PathBuf::new() }
 
     pub fn join_symlink_native_path_and_cygwin_target(&self, _native_path: &Path,
             _cygwin_path: &Path) -> PathBuf { PathBuf::new() }
     pub fn resolve_path(&self, path: &Path) -> PathBuf { PathBuf::from(path) }
+    pub fn resolve_path_with_policy(&self, path: &Path, _policy: SymlinkPolicy) -> PathBuf { PathBuf::from(path) }
+    pub fn resolve_path_cow<'a>(&self, path: &'a Path) -> Cow<'a, Path> { Cow::Borrowed(path) }
+    pub fn resolve_path_traced(&self, path: &Path) -> (PathBuf, Vec<ResolutionStep>) {
+        (PathBuf::from(path), vec![ResolutionStep::NotUnderCygwin])
+    }
+    pub fn resolve_path_within(&self, base: &Path, path: &Path) -> Result<PathBuf, JailError> {
+        let base = jail::normalize_native_path(base);
+        let path = jail::normalize_native_path(&base.join(path));
+        if jail::is_within(&base, &path) { Ok(path) } else { Err(JailError::Escapes(path)) }
+    }
 }
 
-#[cfg(not(windows))]
+#[cfg(all(not(windows), feature = "std"))]
 pub fn maybe_cygwin_symlink(_path: &Path) -> bool { false }
 
 // Implementation
 
 /// An object that remembers the current Cygwin root path,
 /// for use in path resolving operations.
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 #[derive(Clone,Debug)]
 pub struct CygRoot {
     native_path_to_root: PathBuf,
     running_under_cygwin: bool,
+    cygwin_cwd: Option<String>,
+    env: Option<EnvBlock>,
+    cygwin_dll_path: Option<PathBuf>,
+    compat_profile: Option<CompatProfile>,
+    detected_compat_profile: CompatProfile,
+    symlink_codepage: Codepage,
+    charset: Option<Charset>,
+    jail_policy: JailPolicy,
+    symlink_policy: SymlinkPolicy,
+    mounts: Vec<(String, PathBuf)>,
+    cygdrive_prefix: Option<String>,
+    max_symlink_hops: usize,
+    output_style: PathStyle,
+    flavor: Flavor,
 }
 
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 impl CygRoot {
     /// Looks up `cygwin1.dll` in `PATH`, and marks the path two dirs upper as a Cygwin root.
     /// This is because Cygwin keeps the dll in `/bin`.
     pub fn new() -> CygRoot {
-        let env_path = std::env::var_os("PATH");
-        let cygwin_dll_name = Path::new("cygwin1.dll");
-        let mut under_cygwin = false;
-        let root =
-            match find_in_pathlist(&env_path, &cygwin_dll_name) {
-                None => PathBuf::new(),
-                Some(cygwin_dll_path) =>
-                    match cygwin_dll_path.parent() {
-                        None => PathBuf::new(),
-                        Some(bin_path) =>
-                            match bin_path.parent() {
-                                None => PathBuf::new(),
-                                Some(root_path) => {
-                                    under_cygwin = true;
-                                    PathBuf::new().join(root_path)
-                                }
-                            },
-                    },
-            };
-        CygRoot {
-            running_under_cygwin: under_cygwin,
-            native_path_to_root: root,
+        CygRoot::discover(&[RootStrategy::PathScan]).0
+    }
+
+    /// Does what `CygRoot::new()` does, but in the environment `env` instead of the process one.
+    /// Variables needed later (like `PWD` and `HOME`) are copied from `env` too.
+    pub fn from_env(env: &dyn EnvSource) -> CygRoot {
+        CygRoot::discover_in_env(&[RootStrategy::PathScan], env).0
+    }
+
+    /// Tries `strategies` in order (see `RootStrategy::defaults()`), and marks the first root found
+    /// as a Cygwin one, as if running under Cygwin, so that paths get translated.
+    /// Returns every strategy tried and why it has or has not matched.
+    pub fn discover(strategies: &[RootStrategy]) -> (CygRoot, Vec<DiscoveryAttempt>) {
+        let attempts = discovery::discover(strategies, &ProcessEnv);
+        (CygRoot::from_discovery_attempts(&attempts), attempts)
+    }
+
+    /// Does what `CygRoot::discover()` does, but in the environment `env` instead of the process one.
+    pub fn discover_in_env(strategies: &[RootStrategy], env: &dyn EnvSource) -> (CygRoot, Vec<DiscoveryAttempt>) {
+        let attempts = discovery::discover(strategies, env);
+        let mut cygroot = CygRoot::from_discovery_attempts(&attempts);
+        cygroot.env = Some(EnvBlock::snapshot(env, ENV_VARS));
+        (cygroot, attempts)
+    }
+
+    fn from_discovery_attempts(attempts: &[DiscoveryAttempt]) -> CygRoot {
+        match discovery::found_root(attempts) {
+            None => CygRoot::from(PathBuf::new(), false),
+            Some(root) => {
+                let mut cygroot = CygRoot::from(PathBuf::from(root), true);
+                cygroot.cygwin_dll_path = discovery::found_cygwin_dll(attempts).map(PathBuf::from);
+                let is_msys_dll = cygroot.cygwin_dll_path.as_ref()
+                    .and_then(|dll| dll.file_name())
+                    .is_some_and(|name| name.eq_ignore_ascii_case(Flavor::Msys.dll_name()));
+                if is_msys_dll {
+                    cygroot.flavor = Flavor::Msys;
+                }
+                // Detected here, so that converting paths never reads the DLL
+                if let Some(info) = cygroot.runtime_info() {
+                    cygroot.detected_compat_profile = CompatProfile::for_version(info.version);
+                }
+                cygroot
+            },
         }
     }
 
@@ -140,7 +347,21 @@ impl CygRoot {
     pub fn from(native_path_to_root: PathBuf, under_cygwin: bool) -> CygRoot {
         CygRoot {
             running_under_cygwin: under_cygwin,
-            native_path_to_root: native_path_to_root,
+            native_path_to_root,
+            cygwin_cwd: None,
+            env: None,
+            cygwin_dll_path: None,
+            compat_profile: None,
+            detected_compat_profile: CompatProfile::default(),
+            symlink_codepage: Codepage::Utf8,
+            charset: None,
+            jail_policy: JailPolicy::default(),
+            symlink_policy: SymlinkPolicy::default(),
+            mounts: Vec::new(),
+            cygdrive_prefix: None,
+            max_symlink_hops: DEFAULT_MAX_SYMLINK_HOPS,
+            output_style: PathStyle::Windows,
+            flavor: Flavor::Cygwin,
         }
     }
 
@@ -151,121 +372,550 @@ impl CygRoot {
         self.native_path_to_root.as_path()
     }
 
+    /// The environment variables are looked up in.
+    fn env(&self) -> &dyn EnvSource {
+        match self.env {
+            Some(ref env) => env,
+            None => &ProcessEnv,
+        }
+    }
+
+    /// Returns Windows path to the `cygwin1.dll` the root was found by, if any.
+    pub fn cygwin_dll_path(&self) -> Option<&Path> {
+        self.cygwin_dll_path.as_deref()
+    }
+
+    /// Checks that the root has the directories and files of a Cygwin install,
+    /// and that `cygwin1.dll` was found in its `bin`.
+    /// `CygRoot::new()` does no such checks, so a `cygwin1.dll` bundled with some application in `PATH`
+    /// gives a bogus root; callers could refuse to translate paths, or warn, if `is_valid()` is false.
+    pub fn validate(&self) -> RootValidation {
+        discovery::validate_root(&self.native_path_to_root, self.cygwin_dll_path())
+    }
+
+    /// Reads the architecture and version of `self.cygwin_dll_path()` from its PE headers.
+    /// Returns `None` if no DLL was found or it could not be parsed;
+    /// `cygwin_fs::read_runtime_info` tells why.
+    pub fn runtime_info(&self) -> Option<RuntimeInfo> {
+        self.cygwin_dll_path().and_then(|dll| pe::read_runtime_info(dll).ok())
+    }
+
+    /// Returns the behaviour of the Cygwin generation paths are translated for:
+    /// the one given to `set_compat_profile()`, or the one matching `self.runtime_info()`,
+    /// which `CygRoot::new()` reads from the headers of `cygwin1.dll`.
+    /// Without a readable `cygwin1.dll` (as for `CygRoot::from()`), that of the current Cygwin is assumed,
+    /// so special characters are mapped, as `:` of `/tmp/a:b` to U+F03A.
+    pub fn compat_profile(&self) -> CompatProfile {
+        self.profile().clone()
+    }
+
+    /// Makes paths be translated as the given Cygwin generation does,
+    /// like `CompatProfile::cygwin_1_5()` for trees from old installs.
+    /// With `None`, the profile is detected from the version of `cygwin1.dll`.
+    pub fn set_compat_profile(&mut self, profile: Option<CompatProfile>) {
+        self.compat_profile = profile;
+    }
+
+    /// Returns the charset symlink targets without a byte order mark are decoded from.
+    pub fn symlink_codepage(&self) -> Codepage {
+        self.symlink_codepage
+    }
+
+    /// Makes symlink targets without a byte order mark be decoded from `codepage`,
+    /// like the ANSI codepage (`Codepage::ansi()`) for symlinks written by Cygwin 1.5.
+    /// The default is UTF-8, as written by Cygwin 1.7 and later.
+    pub fn set_symlink_codepage(&mut self, codepage: Codepage) {
+        self.symlink_codepage = codepage;
+    }
+
+    /// Returns the charset Cygwin byte paths are in: the one given to `set_charset()`,
+    /// or the one of the locale (`LC_ALL`, `LC_CTYPE` or `LANG`), UTF-8 by default.
+    pub fn charset(&self) -> Charset {
+        match self.charset {
+            Some(charset) => charset,
+            None => Charset::from_env(self.env()),
+        }
+    }
+
+    /// Makes Cygwin byte paths be converted with `charset`, regardless of the locale.
+    /// With `None`, the charset of the locale is used.
+    pub fn set_charset(&mut self, charset: Option<Charset>) {
+        self.charset = charset;
+    }
+
+    /// Returns which symlink targets `resolve_path_within()` follows.
+    pub fn jail_policy(&self) -> JailPolicy {
+        self.jail_policy
+    }
+
+    /// Sets which symlink targets `resolve_path_within()` follows.
+    /// By default, symlinks to drives and network shares are refused.
+    pub fn set_jail_policy(&mut self, policy: JailPolicy) {
+        self.jail_policy = policy;
+    }
+
+    /// Returns which symlinks `resolve_path()` follows.
+    pub fn symlink_policy(&self) -> SymlinkPolicy {
+        self.symlink_policy
+    }
+
+    /// Sets which symlinks `resolve_path()` follows; by default, only the final component.
+    pub fn set_symlink_policy(&mut self, policy: SymlinkPolicy) {
+        self.symlink_policy = policy;
+    }
+
+    /// Returns the mounts added with `add_mount()` or `load_fstab()`, as `(mount point, native directory)`.
+    pub fn mounts(&self) -> &[(String, PathBuf)] {
+        &self.mounts
+    }
+
+    /// Mounts the native directory `native` (like `D:\data`) at `mount_point` (like `/data`),
+    /// replacing an earlier mount there.
+    /// Paths are converted with the longest mount point they are under, of these and the default ones
+    /// of `self.compat_profile()`; a mount at the same mount point as a default one takes precedence.
+    pub fn add_mount(&mut self, mount_point: &str, native: PathBuf) {
+        let mount_point = String::from(mount_point.trim_end_matches('/'));
+        self.mounts.retain(|(mp, _)| *mp != mount_point);
+        self.mounts.push((mount_point, native));
+    }
+
+    /// Adds the mounts and the cygdrive prefix of `/etc/fstab`.
+    /// Returns false if there is no readable `/etc/fstab`.
+    pub fn load_fstab(&mut self) -> bool {
+        let fstab = match self.read_etc_file("fstab") {
+            None => return false,
+            Some(fstab) => fstab,
+        };
+        for entry in fstab::parse_fstab(&fstab) {
+            if entry.is_cygdrive() {
+                self.cygdrive_prefix = Some(entry.mount_point);
+            } else if entry.mount_point != "/" {
+                let mut native = entry.native;
+                backslash_the_slashes_in_string(&mut native);
+                self.add_mount(&entry.mount_point, PathBuf::from(native));
+            }
+        }
+        true
+    }
+
+    /// Returns the prefix of paths to drives, like `/cygdrive` in `/cygdrive/c`:
+    /// the one given to `set_cygdrive_prefix()`, `/` for MSYS, or that of `self.compat_profile()`.
+    pub fn cygdrive_prefix(&self) -> &str {
+        match self.cygdrive_prefix {
+            Some(ref prefix) => prefix.as_str(),
+            None if self.flavor == Flavor::Msys => "/",
+            None => self.profile().cygdrive_prefix.as_str(),
+        }
+    }
+
+    /// Sets the prefix of paths to drives, like `/mnt` for `/mnt/c`.
+    /// With `None`, the default of the flavor and the compatibility profile is used.
+    pub fn set_cygdrive_prefix(&mut self, prefix: Option<String>) {
+        self.cygdrive_prefix = prefix;
+    }
+
+    /// Returns how many symlinks are followed in a row before giving up.
+    pub fn max_symlink_hops(&self) -> usize {
+        self.max_symlink_hops
+    }
+
+    /// Sets how many symlinks are followed in a row before giving up (`DEFAULT_MAX_SYMLINK_HOPS` by default).
+    pub fn set_max_symlink_hops(&mut self, hops: usize) {
+        self.max_symlink_hops = hops;
+    }
+
+    /// Returns how native paths are written by `convert_path_to_native()` and `resolve_path()`.
+    pub fn output_style(&self) -> PathStyle {
+        self.output_style
+    }
+
+    /// Makes native paths be written with backslashes (`PathStyle::Windows`, the default),
+    /// or with slashes (`PathStyle::Mixed`). `PathStyle::Unix` is treated as `PathStyle::Windows`.
+    pub fn set_output_style(&mut self, style: PathStyle) {
+        self.output_style = style;
+    }
+
+    /// Returns whether the root is a Cygwin or an MSYS2 one.
+    pub fn flavor(&self) -> Flavor {
+        self.flavor
+    }
+
+    /// Marks the root as a Cygwin or an MSYS2 one, which changes the default cygdrive prefix.
+    pub fn set_flavor(&mut self, flavor: Flavor) {
+        self.flavor = flavor;
+    }
+
+    fn apply_output_style(&self, native: PathBuf) -> PathBuf {
+        match self.output_style {
+            PathStyle::Mixed => {
+                let mut native_b = native.into_os_string().into_encoded_bytes();
+                slash_the_backslashes_in_bytes(&mut native_b);
+                PathBuf::from(os_string_from_wtf8(native_b))
+            },
+            PathStyle::Windows | PathStyle::Unix => native,
+        }
+    }
+
+    fn profile(&self) -> &CompatProfile {
+        match self.compat_profile {
+            Some(ref profile) => profile,
+            None => &self.detected_compat_profile,
+        }
+    }
+
     /// Whether running in a Cygwin shell.
     /// This involves no check; everything is done in `CygRoot::new()`.
     pub fn running_under_cygwin(&self) -> bool {
         self.running_under_cygwin
     }
 
+    /// Returns the Cygwin working directory that relative paths are resolved against, if any.
+    pub fn cwd(&self) -> Option<&str> {
+        match self.cygwin_cwd {
+            None => None,
+            Some(ref cwd) => Some(cwd.as_str()),
+        }
+    }
+
+    /// Sets the Cygwin working directory (like `/home/user/src`) that `resolve_path` interprets
+    /// relative paths against. With `None`, relative paths are left to Windows, as by default.
+    /// Paths not starting with `/` are ignored, as Cygwin never has such a working directory.
+    pub fn set_cwd(&mut self, cwd: Option<&str>) {
+        self.cygwin_cwd = match cwd {
+            Some(cwd) if cwd.starts_with("/") => Some(normalize_posix_path(cwd)),
+            _ => None,
+        };
+    }
+
+    /// Takes the Cygwin working directory from `PWD`, which the Cygwin shell exports
+    /// to the programs it runs.
+    /// This is the logical directory, so it could have been reached through a symlink.
+    pub fn set_cwd_from_pwd(&mut self) {
+        let pwd = self.env().var_os("PWD");
+        let pwd_s = pwd.as_ref().map(|s| s.to_string_lossy().into_owned());
+        self.set_cwd(pwd_s.as_deref());
+    }
+
     /// Converts `/cygwin/path` to `C:\native\one`, without following symlinks.
+    /// The cygdrive prefix, default mounts and special characters are those of `self.compat_profile()`.
     /// Should only be called if `self.running_under_cygwin()` returns true.
     pub fn convert_path_to_native(&self, path: &str) -> PathBuf {
-        let path_b = path.as_bytes();
-        let mut ret = PathBuf::new();
-        let mut cygdrive_end = 0;
-        if path.starts_with("/") {
-            let mut cygdrive = '\0';
-            let path_blen = path_b.len();
-            // Check for /cygdrive/drive_letter/
-            if let Some(path_1) = eat_chars(path, '/') {
-                if let Some(path_11) = eat_str(path_1, "cygdrive") {
-                    if let Some(path_2) = eat_chars(path_11, '/') {
-                        if let Some((drive_letter, path_22)) = pop_char(path_2) {
-                            if valid_drive_letter(drive_letter) {
-                                if path_22.len() == 0 {
-                                    cygdrive = ascii_upcase(drive_letter);
-                                    cygdrive_end = path_blen;
-                                } else {
-                                    if let Some(path_3) = eat_chars(path_22, '/') {
-                                        cygdrive = ascii_upcase(drive_letter);
-                                        cygdrive_end = path_blen - path_3.as_bytes().len();
-                                    }
-                                }
-                            }
+        self.convert_path_to_native_os(path)
+    }
+
+    /// Does what `convert_path_to_native()` does, keeping names that are not valid Unicode
+    /// (like ones with unpaired surrogates) as they are.
+    pub fn convert_path_to_native_os<P: AsRef<OsStr>>(&self, path: P) -> PathBuf {
+        self.apply_output_style(self.native_path_of(path.as_ref()))
+    }
+
+    /// Does what `convert_path_to_native_os()` does, regardless of `self.output_style()`.
+    fn native_path_of(&self, path: &OsStr) -> PathBuf {
+        self.native_path_traced(path, &mut Trace::off())
+    }
+
+    fn native_path_traced(&self, path: &OsStr, trace: &mut Trace) -> PathBuf {
+        let mut native_b = Vec::<u8>::with_capacity(self.native_path_to_root.as_os_str().len() + path.len() + 8);
+        self.write_native_path(path, &mut native_b, trace);
+        PathBuf::from(os_string_from_wtf8(native_b))
+    }
+
+    /// Appends the native path of `path` to `out`, as WTF-8, regardless of `self.output_style()`.
+    fn write_native_path(&self, path: &OsStr, out: &mut Vec<u8>, trace: &mut Trace) {
+        let path_b = path.as_encoded_bytes();
+        let mut prefix_end = 0;
+        if path_b.starts_with(b"/") {
+            match self.find_cygdrive(path_b) {
+                Some((drive_letter, cygdrive_end)) => {
+                    trace.push(|| ResolutionStep::Cygdrive {
+                        prefix: String::from(self.cygdrive_prefix()),
+                        drive: drive_letter,
+                    });
+                    // Drive letters are ASCII
+                    out.extend_from_slice(&[drive_letter as u8, b':', b'\\']);
+                    prefix_end = cygdrive_end;
+                },
+                None => match self.find_mount(path_b) {
+                    Some((mount_point_len, mount_base, dir_under_base)) => {
+                        let mount_native_start = out.len();
+                        out.extend_from_slice(mount_base.as_os_str().as_encoded_bytes());
+                        for component in dir_under_base.split('/').filter(|c| !c.is_empty()) {
+                            push_native_component(out, component.as_bytes());
                         }
-                    }
-                }
+                        trace.push(|| ResolutionStep::Mount {
+                            mount_point: String::from_utf8_lossy(&path_b[..mount_point_len]).into_owned(),
+                            native: PathBuf::from(os_string_from_wtf8(out[mount_native_start..].to_vec())),
+                        });
+                        prefix_end = mount_point_len;
+                    },
+                    None => {
+                        trace.push(|| ResolutionStep::Root(self.native_path_to_root.clone()));
+                        out.extend_from_slice(self.native_path_to_root.as_os_str().as_encoded_bytes());
+                    },
+                },
             }
-            if cygdrive != '\0' {
-                ret.push(format!("{}:\\", cygdrive));
-            } else {
-                ret.push(&self.native_path_to_root);
+        }
+        for component in grammar::components(&path_b[prefix_end..]) {
+            // Split at ASCII slashes, and only ASCII characters mapped, so still WTF-8
+            push_native_component(out, &self.map_special_chars(component));
+        }
+        trace.push(|| ResolutionStep::Converted(PathBuf::from(os_string_from_wtf8(out.clone()))));
+    }
+
+    /// Does what `convert_path_to_native_os()` does, writing the native path into `out` (replacing what it had),
+    /// so that converting many paths with the same buffer allocates only as the buffer grows.
+    pub fn convert_path_to_native_into<P: AsRef<OsStr>>(&self, path: P, out: &mut OsString) {
+        let mut native_b = mem::take(out).into_encoded_bytes();
+        native_b.clear();
+        self.write_native_path(path.as_ref(), &mut native_b, &mut Trace::off());
+        if self.output_style == PathStyle::Mixed {
+            slash_the_backslashes_in_bytes(&mut native_b);
+        }
+        *out = os_string_from_wtf8(native_b);
+    }
+
+    /// Converts a Cygwin byte path (as passed between Cygwin programs) to `C:\native\one`,
+    /// decoding it with `self.charset()`.
+    /// Should only be called if `self.running_under_cygwin()` returns true.
+    pub fn convert_bytes_to_native(&self, path: &[u8]) -> PathBuf {
+        self.convert_path_to_native_os(self.charset().decode(path))
+    }
+
+    /// Parses `/cygdrive/d/path`, returning the upper-case drive letter, and where `/path` starts.
+    fn find_cygdrive(&self, path: &[u8]) -> Option<(char, usize)> {
+        grammar::parse_cygdrive(path, self.cygdrive_prefix())
+    }
+
+    /// Finds the longest mount `path` is under,
+    /// returning the length of the mount point, and the native directory it is mounted from,
+    /// as a native directory and a Cygwin one under it (as `/bin` under the root for `/usr/bin`).
+    fn find_mount(&self, path: &[u8]) -> Option<(usize, &Path, &str)> {
+        let mut ret: Option<(usize, &Path, &str)> = None;
+        for (mount_point, native) in self.mounts.iter() {
+            if !mount_point.is_empty() && path_is_under(path, mount_point.as_bytes())
+                    && ret.is_none_or(|(len, _, _)| len < mount_point.len()) {
+                ret = Some((mount_point.len(), native.as_path(), ""));
             }
         }
-        let mut last_was_slash = false;
-        let mut beg_path_comp = 0;
-        let mut just_past_cygdrive = false;
-        for (i, ch) in path.char_indices() {
-            if i < cygdrive_end {
-                just_past_cygdrive = true;
-                beg_path_comp = i;
+        // A default mount wins over a shorter added one, as `/usr/bin` over `/usr`
+        for (mount_point, dir_under_root) in self.profile().default_mounts.iter() {
+            let mount_point = mount_point.trim_end_matches('/');
+            if mount_point.is_empty() || !path_is_under(path, mount_point.as_bytes()) {
                 continue;
             }
-            if just_past_cygdrive {
-                just_past_cygdrive = false;
-                last_was_slash = true;
-                beg_path_comp = i;
+            if ret.is_none_or(|(len, _, _)| len < mount_point.len()) {
+                ret = Some((mount_point.len(), self.native_path_to_root.as_path(), dir_under_root.as_str()));
             }
-            if ch == '/' || ch == '\\' {
-                if !last_was_slash {
-                    if i > beg_path_comp {
-                        let path_component = unsafe {
-                            ::std::str::from_utf8_unchecked(&path_b[beg_path_comp..i])
-                        };
-                        ret.push(path_component);
-                    }
-                    last_was_slash = true;
+        }
+        ret
+    }
+
+    fn map_special_chars<'a>(&self, name: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.profile().special_char_mapping {
+            grammar::map_special_chars(name)
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// Converts `C:\native\path` to `/cygwin/one`: paths under `self.root_path()` to `/path`,
+    /// other drives to `/cygdrive/d/path` (with the cygdrive prefix of `self.compat_profile()`),
+    /// and UNC paths to `//server/share/path`.
+    /// Relative paths only get their backslashes turned into slashes.
+    /// Names that are not valid Unicode are replaced lossily; see `convert_path_to_cygwin_os()`.
+    pub fn convert_path_to_cygwin(&self, path: &Path) -> String {
+        match self.convert_path_to_cygwin_os(path).into_string() {
+            Ok(s) => s,
+            Err(os) => os.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Does what `convert_path_to_cygwin()` does, returning a Cygwin byte path in `self.charset()`.
+    pub fn convert_path_to_cygwin_bytes(&self, path: &Path) -> Vec<u8> {
+        self.charset().encode(&self.convert_path_to_cygwin(path))
+    }
+
+    /// Does what `convert_path_to_cygwin()` does, keeping names that are not valid Unicode as they are.
+    pub fn convert_path_to_cygwin_os(&self, path: &Path) -> OsString {
+        let mut cygwin_b = Vec::<u8>::with_capacity(path.as_os_str().len() + 16);
+        self.write_cygwin_path(path, &mut cygwin_b);
+        os_string_from_wtf8(cygwin_b)
+    }
+
+    /// Does what `convert_path_to_cygwin()` does, writing the Cygwin path into `out` (replacing what it had),
+    /// so that converting many paths with the same buffer allocates only as the buffer grows.
+    pub fn convert_path_to_cygwin_into(&self, path: &Path, out: &mut String) {
+        let mut cygwin_b = mem::take(out).into_bytes();
+        cygwin_b.clear();
+        self.write_cygwin_path(path, &mut cygwin_b);
+        *out = match String::from_utf8(cygwin_b) {
+            Ok(s) => s,
+            Err(err) => os_string_from_wtf8(err.into_bytes()).to_string_lossy().into_owned(),
+        };
+    }
+
+    /// Does what `convert_path_to_cygwin_os()` does, borrowing `path` if it needs no change,
+    /// as relative paths without backslashes (like `src/main.rs`).
+    pub fn convert_path_to_cygwin_cow<'a>(&self, path: &'a Path) -> Cow<'a, OsStr> {
+        let path_b = path.as_os_str().as_encoded_bytes();
+        let unchanged = is_relative_cygwin_path(path) && !path_b.contains(&b'\\')
+            && (!self.profile().special_char_mapping || matches!(grammar::unmap_special_chars(path_b), Cow::Borrowed(_)));
+        if unchanged {
+            Cow::Borrowed(path.as_os_str())
+        } else {
+            Cow::Owned(self.convert_path_to_cygwin_os(path))
+        }
+    }
+
+    /// Appends the Cygwin path of `path` to the empty `out`, as WTF-8.
+    fn write_cygwin_path(&self, path: &Path, out: &mut Vec<u8>) {
+        out.extend_from_slice(path.as_os_str().as_encoded_bytes());
+        slash_the_backslashes_in_bytes(out);
+        if out.starts_with(b"//?/UNC/") {
+            out.splice(..8, b"//".iter().cloned());
+        } else if out.starts_with(b"//?/") {
+            out.drain(..4);
+        }
+        if self.profile().special_char_mapping {
+            if let Cow::Owned(unmapped) = grammar::unmap_special_chars(out) {
+                *out = unmapped;
+            }
+        }
+        let mut mount_match: Option<(&str, usize)> = None;
+        for (mount_point, native) in self.mounts.iter() {
+            if let Some(native_len) = native_prefix_len(out, native) {
+                if mount_match.is_none_or(|(_, len)| len < native_len) {
+                    mount_match = Some((mount_point.as_str(), native_len));
                 }
-                beg_path_comp = i;
-                continue;
             }
-            if last_was_slash {
-                last_was_slash = false;
-                beg_path_comp = i;
+        }
+        if let Some((mount_point, native_len)) = mount_match {
+            let rest_len = out[native_len..].iter().rposition(|&b| b != b'/').map_or(0, |i| i + 1);
+            out.truncate(native_len + rest_len);
+            out.splice(..native_len, mount_point.bytes());
+            if out.is_empty() {
+                out.push(b'/');
             }
+            return;
         }
-        if beg_path_comp < path_b.len() - 1 {
-            let final_path_component = unsafe {
-                ::std::str::from_utf8_unchecked(&path_b[beg_path_comp..])
-            };
-            ret.push(final_path_component);
+        if let Some(root_len) = native_prefix_len(out, &self.native_path_to_root) {
+            let rest_len = out[root_len..].iter().rposition(|&b| b != b'/').map_or(0, |i| i + 1);
+            out.truncate(root_len + rest_len);
+            out.drain(..root_len);
+            if out.is_empty() {
+                out.push(b'/');
+            }
+            return;
+        }
+        if out.len() >= 2 && out[1] == b':' && valid_drive_letter(out[0] as char) {
+            let drive_letter = out[0].to_ascii_lowercase();
+            let rest = &out[2..];
+            let rest_beg = rest.iter().position(|&b| b != b'/').unwrap_or(rest.len());
+            let rest_end = rest.iter().rposition(|&b| b != b'/').map_or(rest_beg, |i| i + 1);
+            let has_rest = rest_beg < rest_end;
+            out.truncate(2 + rest_end);
+            let head = self.cygdrive_prefix().trim_end_matches('/').bytes()
+                .chain(once(b'/'))
+                .chain(once(drive_letter))
+                .chain(if has_rest { Some(b'/') } else { None });
+            out.splice(..2 + rest_beg, head);
+        }
+    }
+
+    /// Returns a Windows special folder (like the desktop) in the given style, as `cygpath -D` and the like do.
+    /// The folder is computed from the environment `CygRoot` was made in.
+    pub fn special_folder(&self, folder: SpecialFolder, style: PathStyle) -> Option<PathBuf> {
+        self.special_folder_in_env(folder, style, self.env())
+    }
+
+    /// Returns a Windows special folder in the given style,
+    /// looking up environment variables (like `USERPROFILE`) in `env`.
+    pub fn special_folder_in_env(&self, folder: SpecialFolder, style: PathStyle,
+            env: &dyn EnvSource) -> Option<PathBuf> {
+        let mut native = folders::special_folder_path(folder, env)?;
+        match style {
+            PathStyle::Windows => Some(PathBuf::from(native)),
+            PathStyle::Mixed => {
+                slash_the_backslashes_in_string(&mut native);
+                Some(PathBuf::from(native))
+            },
+            PathStyle::Unix => Some(PathBuf::from(self.convert_path_to_cygwin(Path::new(&native)))),
         }
-        ret
     }
 
     /// Retrieves contents of a `C:\cygwin\symlink` file
     /// Should only be called if both `self.running_under_cygwin()` and `maybe_cygwin_symlink(path)` return true.
+    /// Only the formats of `self.compat_profile()` are recognized.
+    /// Returns `None` for targets that cannot be decoded; `try_read_symlink_contents()` tells why.
+    /// Only the magic of other files is read, and nothing of ones larger than `MAX_SYMLINK_SIZE`.
     pub fn read_symlink_contents(&self, path: &Path) -> Option<PathBuf> {
-        let mut fdata = Vec::<u8>::with_capacity(64);
-        match File::open(path) {
-            Err(_) => return None,
-            Ok(mut f) => {
-                match f.read_to_end(&mut fdata) {
-                    Err(_) => return None,
-                    Ok(_) => {
-                        let filemagic = b"!<symlink>";
-                        if !fdata.as_slice().starts_with(filemagic) {
-                            return None
-                        } else {
-                            let data_after_magic = &fdata[filemagic.len()..];
-                            let string16_in_file = string_from_utf_bom_lossy(data_after_magic);
-                            let path16_in_file = PathBuf::from(&string16_in_file);
-                            return Some(path16_in_file)
-                        }
-                    }
-                }
-            }
+        self.try_read_symlink_contents(path).unwrap_or(None)
+    }
+
+    /// Does what `read_symlink_contents()` does, failing for symlinks whose target cannot be decoded
+    /// (see `decode_symlink_target()`).
+    /// Returns `Ok(None)` for unreadable files, and for files that are not symlinks of a format of `self.compat_profile()`.
+    pub fn try_read_symlink_contents(&self, path: &Path) -> Result<Option<PathBuf>, DecodeError> {
+        if self.profile().reads(SymlinkFormat::Wsl) && is_wsl_symlink(path) {
+            return match read_wsl_symlink(path) {
+                None => Ok(None),
+                Some(target) => decode_symlink_target(&target, Codepage::Utf8).map(|target| Some(PathBuf::from(target))),
+            };
+        }
+        let mut f = match File::open(path) {
+            Err(_) => return Ok(None),
+            Ok(f) => f,
+        };
+        let file_len = match f.metadata() {
+            Err(_) => return Ok(None),
+            Ok(metadata) => metadata.len(),
+        };
+        match decode::read_symlink_data(&mut f, file_len) {
+            Err(_) | Ok(None) => Ok(None),
+            Ok(Some(data_after_magic)) => self.decode_symlink_data(&data_after_magic),
+        }
+    }
+
+    /// Whether `path` could be a symlink of a format of `self.compat_profile()`:
+    /// a system file, as `maybe_cygwin_symlink()` says, or a WSL symlink.
+    /// Other reparse points, like junctions and cloud files, are neither opened nor reported.
+    fn could_be_symlink(&self, path: &Path) -> bool {
+        match file_attributes(path) {
+            None => false,
+            Some(attr) if (attr & FILE_ATTRIBUTE_SYSTEM) != 0 => true,
+            Some(attr) => (attr & FILE_ATTRIBUTE_REPARSE_POINT) != 0
+                && self.profile().reads(SymlinkFormat::Wsl) && is_wsl_symlink(path),
+        }
+    }
+
+    /// Decodes what follows the magic of a symlink file, if its format is one of `self.compat_profile()`.
+    fn decode_symlink_data(&self, data_after_magic: &[u8]) -> Result<Option<PathBuf>, DecodeError> {
+        let has_bom = data_after_magic.starts_with(&[0xff, 0xfe]) || data_after_magic.starts_with(&[0xfe, 0xff]);
+        let format = if has_bom { SymlinkFormat::MagicUtf16 } else { SymlinkFormat::MagicBytes };
+        if !self.profile().reads(format) {
+            return Ok(None)
         }
+        decode_symlink_target(data_after_magic, self.symlink_codepage).map(|target| Some(PathBuf::from(target)))
     }
 
     /// Follows `C:\cygwin\symlink` once, returning `C:\cygwin\target`
     /// If path to the cygwin symlink is relative, return value is relative too.
     /// Should only be called if both `self.running_under_cygwin()` and `maybe_cygwin_symlink(path)` return true.
     pub fn resolve_symlink_once(&self, path: &Path) -> PathBuf {
-        match self.read_symlink_contents(path) {
-            None => return PathBuf::from(path),
+        self.resolve_symlink_once_traced(path, self, &mut Trace::off())
+    }
+
+    fn resolve_symlink_once_traced(&self, path: &Path, probe: &dyn SymlinkProbe, trace: &mut Trace) -> PathBuf {
+        match probe.read_symlink(path) {
+            None => {
+                trace.push(|| ResolutionStep::SymlinkUnreadable(PathBuf::from(path)));
+                PathBuf::from(path)
+            },
             Some(cygwin_target) => {
-                return self.join_symlink_native_path_and_cygwin_target(path, &cygwin_target.as_path())
+                trace.push(|| ResolutionStep::SymlinkRead { link: PathBuf::from(path), target: cygwin_target.clone() });
+                let dest = self.join_symlink_native_path_and_cygwin_target(path, cygwin_target.as_path());
+                trace.push(|| ResolutionStep::Joined(dest.clone()));
+                dest
             }
         }
     }
@@ -273,256 +923,622 @@ impl CygRoot {
     /// Follows `C:\cygwin\symlink` as many times as needed, returning `C:\cygwin\target`
     /// If path to the cygwin symlink is relative, return value is relative too
     /// (unless a further symlink points to an absolute path).
+    /// A relative target is relative to the symlink it was read from, not to `path`.
+    /// Gives up after `self.max_symlink_hops()` hops, as on a cycle, returning the last one.
     /// Should only be called if both `self.running_under_cygwin()` and `maybe_cygwin_symlink(path)` return true.
     pub fn resolve_symlink(&self, path: &Path) -> PathBuf {
+        self.resolve_symlink_traced(path, self, &mut Trace::off())
+    }
+
+    fn resolve_symlink_traced(&self, path: &Path, probe: &dyn SymlinkProbe, trace: &mut Trace) -> PathBuf {
         let mut dest = PathBuf::from(path);
         let mut first_iteration = true;
-        loop {
+        for _ in 0..self.max_symlink_hops {
             if first_iteration {
                 first_iteration = false
             } else {
-                if !maybe_cygwin_symlink(&dest.as_path()) {
+                if !probe.maybe_symlink(dest.as_path()) {
+                    trace.push(|| ResolutionStep::NotASymlink(dest.clone()));
                     return dest
                 }
             }
-            match self.read_symlink_contents(&dest.as_path()) {
-                None => return dest,
+            match probe.read_symlink(dest.as_path()) {
+                None => {
+                    trace.push(|| ResolutionStep::SymlinkUnreadable(dest.clone()));
+                    return dest
+                },
                 Some(cygwin_target) => {
-                    dest = self.join_symlink_native_path_and_cygwin_target(path, &cygwin_target.as_path())
+                    trace.push(|| ResolutionStep::SymlinkRead { link: dest.clone(), target: cygwin_target.clone() });
+                    dest = self.join_symlink_native_path_and_cygwin_target(dest.as_path(), cygwin_target.as_path());
+                    trace.push(|| ResolutionStep::Joined(dest.clone()));
                 }
             }
         }
+        trace.push(|| ResolutionStep::HopLimit(dest.clone()));
+        dest
     }
 
     /// Concatenates `C:\cygwin\dir1\symlink` with:
     /// - `dir2/target` into `C:\cygwin\dir1\dir2\target`
     /// - `/dir2/target` into `C:\cygwin\dir2\target`
     /// - `/cygdrive/d/dir2/target` into `D:\dir2\target`
+    ///
     /// Bugs:
     /// - `../../../../target` into `C:\target`, not `C:\cygwin\target`
     /// - `../../../../cygdrive/d` into `C:\cygdrive\d`, not `D:\`
     ///
     /// Should only be called with `cfg!(windows)`.
     pub fn join_symlink_native_path_and_cygwin_target(&self, native_path: &Path, cygwin_path: &Path) -> PathBuf {
-        let mut cygwin_path_s = cygwin_path.as_os_str().to_string_lossy().into_owned();
         if cygwin_path.starts_with("/") {
-            return self.convert_path_to_native(&cygwin_path_s.as_str());
+            self.native_path_of(cygwin_path.as_os_str())
         } else {
-            backslash_the_slashes_in_string(&mut cygwin_path_s);
+            let mut cygwin_path_b = cygwin_path.as_os_str().as_encoded_bytes().to_vec();
+            backslash_the_slashes_in_bytes(&mut cygwin_path_b);
+            let cygwin_path_os = os_string_from_wtf8(cygwin_path_b);
             match native_path.parent() {
-                None => PathBuf::from(cygwin_path_s),
-                Some(dir) => dir.join(cygwin_path_s),
+                None => PathBuf::from(cygwin_path_os),
+                Some(dir) => dir.join(cygwin_path_os),
+            }
+        }
+    }
+
+    /// Reads `C:\cygwin\etc\name` into a string.
+    fn read_etc_file(&self, name: &str) -> Option<String> {
+        let path = self.native_path_to_root.join("etc").join(name);
+        let mut contents = String::new();
+        match File::open(&path) {
+            Err(_) => None,
+            Ok(mut f) => {
+                match f.read_to_string(&mut contents) {
+                    Err(_) => None,
+                    Ok(_) => Some(contents),
+                }
+            }
+        }
+    }
+
+    /// Returns the Cygwin home directory (like `/home/user`) of `user`,
+    /// or of the current user if `None`.
+    /// The current user's one is taken from `HOME`.
+    /// Otherwise, `/etc/passwd` is looked up, falling back to `db_home` of `/etc/nsswitch.conf`.
+    pub fn home_dir(&self, user: Option<&str>) -> Option<String> {
+        let mut user_context = UserContext::from_env(self.env());
+        match user {
+            None => {
+                if let Some(home) = self.env().var_os("HOME") {
+                    if !home.is_empty() {
+                        return Some(home.to_string_lossy().into_owned());
+                    }
+                }
+            },
+            Some(user) => {
+                if user != user_context.cygwin_user.as_str() {
+                    user_context = UserContext {
+                        cygwin_user: String::from(user),
+                        windows_user: String::from(user),
+                        domain: user_context.domain,
+                        ..UserContext::default()
+                    };
+                }
+            },
+        }
+        if let Some(passwd) = self.read_etc_file("passwd") {
+            if let Some(home) = home::passwd_home_dir(passwd.as_str(), user_context.cygwin_user.as_str()) {
+                return Some(String::from(home));
+            }
+        }
+        self.db_home_dir(&user_context)
+    }
+
+    /// Computes the home directory of `user` as Cygwin does without `/etc/passwd`,
+    /// following the `db_home` schemes of `/etc/nsswitch.conf` (`cygwin desc` if unset, then `/home/%U`).
+    pub fn db_home_dir(&self, user: &UserContext) -> Option<String> {
+        let nsswitch = self.read_etc_file("nsswitch.conf");
+        home::db_home_dir(nsswitch.as_deref(), user, &|p| self.convert_path_to_cygwin(p))
+    }
+
+    /// Expands `~` and `~user` at the start of a Cygwin path, as the shell does.
+    /// Paths without a tilde are returned as is.
+    /// Returns `None` if the home directory is unknown.
+    pub fn expand_tilde(&self, path: &str) -> Option<String> {
+        match home::split_tilde_prefix(path) {
+            None => Some(String::from(path)),
+            Some((user, rest)) => {
+                let user = if user.is_empty() { None } else { Some(user) };
+                match self.home_dir(user) {
+                    None => None,
+                    Some(mut home) => {
+                        if home.ends_with("/") && rest.starts_with("/") {
+                            home.pop();
+                        }
+                        home.push_str(rest);
+                        Some(home)
+                    },
+                }
+            },
+        }
+    }
+
+    /// Converts `~/cygwin/path` or `~user/cygwin/path` to `C:\native\one`, without following symlinks.
+    /// Should only be called if `self.running_under_cygwin()` returns true.
+    pub fn expand_tilde_to_native(&self, path: &str) -> Option<PathBuf> {
+        self.expand_tilde(path).map(|p| self.convert_path_to_native(p.as_str()))
+    }
+
+    /// Follows Cygwin symlinks in every directory of `C:\cygwin\dir\symlink\file`,
+    /// but not in the final component.
+    fn resolve_parent_dirs(&self, native_path: &Path, probe: &dyn SymlinkProbe, trace: &mut Trace) -> PathBuf {
+        let mut components = native_path.components();
+        let final_component = components.next_back();
+        let mut ret = PathBuf::new();
+        for component in components {
+            ret.push(component.as_os_str());
+            match component {
+                Component::Normal(_) => {},
+                _ => continue,
             }
+            if probe.maybe_symlink(ret.as_path()) {
+                ret = self.resolve_symlink_traced(ret.as_path(), probe, trace);
+            }
+        }
+        if let Some(component) = final_component {
+            ret.push(component.as_os_str());
         }
+        ret
     }
 
-    /// Converts `/cygwin/path` to `C:\native\one`, following Cygwin symlinks.
+    /// Converts a path relative to `self.cwd()` to a native one.
+    /// As in Cygwin, `..` is applied to the logical path, before looking at symlinks.
+    /// Symlinks in directories are then followed, unless `policy` is `Never`.
+    fn convert_relative_path_to_native(&self, cwd: &str, relative_path: &OsStr, policy: SymlinkPolicy,
+            probe: &dyn SymlinkProbe, trace: &mut Trace) -> PathBuf {
+        let cygwin_path = join_cygwin_cwd(cwd, relative_path);
+        trace.push(|| ResolutionStep::RelativeToCwd { cwd: String::from(cwd), path: PathBuf::from(&cygwin_path) });
+        let p_native = self.native_path_traced(&cygwin_path, trace);
+        match policy {
+            SymlinkPolicy::Never => p_native,
+            _ => self.resolve_parent_dirs(p_native.as_path(), probe, trace),
+        }
+    }
+
+    /// Converts `/cygwin/path` to `C:\native\one`, following Cygwin symlinks as `self.symlink_policy()` says.
+    /// Relative paths are interpreted against `self.cwd()` if it is set (producing absolute paths),
+    /// and are passed as is otherwise.
     /// Return value could be relative, as in `resolve_symlink`.
     /// Could be called without being wrapped in any checks (unlike other methods), even not on `cfg!(windows)`.
     pub fn resolve_path(&self, p: &Path) -> PathBuf {
+        self.resolve_path_with_policy(p, self.symlink_policy)
+    }
+
+    /// Does what `resolve_path()` does, following symlinks as `policy` says.
+    pub fn resolve_path_with_policy(&self, p: &Path, policy: SymlinkPolicy) -> PathBuf {
         if !self.running_under_cygwin { return PathBuf::from(p) }
+        self.apply_output_style(self.resolve_native_path(p, policy, self, &mut Trace::off()))
+    }
+
+    /// Does what `resolve_path()` does, borrowing `p` if it is returned unchanged,
+    /// as when not running under Cygwin, or for native paths that are not symlinks.
+    pub fn resolve_path_cow<'a>(&self, p: &'a Path) -> Cow<'a, Path> {
+        if !self.running_under_cygwin || self.resolves_to_itself(p) {
+            Cow::Borrowed(p)
+        } else {
+            Cow::Owned(self.resolve_path(p))
+        }
+    }
+
+    /// Whether `resolve_path()` would return `p` as is: a native path (or a relative one without `self.cwd()`),
+    /// not followed as a symlink, in `self.output_style()`.
+    fn resolves_to_itself(&self, p: &Path) -> bool {
+        if p.starts_with("/") || (self.cygwin_cwd.is_some() && is_relative_cygwin_path(p)) {
+            return false;
+        }
+        if self.output_style == PathStyle::Mixed && p.as_os_str().as_encoded_bytes().contains(&b'\\') {
+            return false;
+        }
+        match self.symlink_policy {
+            SymlinkPolicy::Never => true,
+            SymlinkPolicy::FinalOnly | SymlinkPolicy::FinalOnce => !self.could_be_symlink(p),
+            SymlinkPolicy::AllComponents => false,
+        }
+    }
+
+    /// Does what `resolve_path()` does, also returning every step taken,
+    /// from the mount matched to the symlinks read, to explain the result.
+    pub fn resolve_path_traced(&self, p: &Path) -> (PathBuf, Vec<ResolutionStep>) {
+        let mut steps = Vec::new();
+        if !self.running_under_cygwin {
+            steps.push(ResolutionStep::NotUnderCygwin);
+            return (PathBuf::from(p), steps);
+        }
+        steps.push(ResolutionStep::Policy(self.symlink_policy));
+        let p_native = self.resolve_native_path(p, self.symlink_policy, self, &mut Trace::to(&mut steps));
+        let ret = self.apply_output_style(p_native);
+        steps.push(ResolutionStep::Resolved(ret.clone()));
+        (ret, steps)
+    }
+
+    /// Does what `resolve_path_with_policy()` does, looking at files through `probe`, like a cache.
+    fn resolve_path_probed(&self, p: &Path, policy: SymlinkPolicy, probe: &dyn SymlinkProbe) -> PathBuf {
+        if !self.running_under_cygwin { return PathBuf::from(p) }
+        self.apply_output_style(self.resolve_native_path(p, policy, probe, &mut Trace::off()))
+    }
+
+    fn resolve_native_path(&self, p: &Path, policy: SymlinkPolicy, probe: &dyn SymlinkProbe,
+            trace: &mut Trace) -> PathBuf {
         let p_native =
             if p.starts_with("/") {
-                let p_s = p.as_os_str().to_string_lossy().into_owned();
-                self.convert_path_to_native(&p_s.as_str())
+                self.native_path_traced(p.as_os_str(), trace)
+            } else {
+                match self.cygwin_cwd {
+                    Some(ref cwd) if is_relative_cygwin_path(p) => {
+                        self.convert_relative_path_to_native(cwd.as_str(), p.as_os_str(), policy, probe, trace)
+                    },
+                    _ => {
+                        trace.push(|| ResolutionStep::NotConverted(PathBuf::from(p)));
+                        PathBuf::from(p)
+                    },
+                }
+            };
+        let p_native = match policy {
+            SymlinkPolicy::Never => return p_native,
+            SymlinkPolicy::AllComponents => self.resolve_parent_dirs(p_native.as_path(), probe, trace),
+            SymlinkPolicy::FinalOnly | SymlinkPolicy::FinalOnce => p_native,
+        };
+        if !probe.maybe_symlink(p_native.as_path()) {
+            trace.push(|| ResolutionStep::NotASymlink(p_native.clone()));
+            return p_native
+        }
+        match policy {
+            SymlinkPolicy::FinalOnce => self.resolve_symlink_once_traced(p_native.as_path(), probe, trace),
+            _ => self.resolve_symlink_traced(p_native.as_path(), probe, trace),
+        }
+    }
+
+    /// Does what `resolve_path()` does, but fails if the path, or any symlink hop on the way,
+    /// leads outside of `base` (a native directory like `C:\cygwin\home\build`, or a Cygwin one like `/home/build`).
+    /// Relative paths are interpreted against `base`, rather than `self.cwd()`.
+    /// Symlinks to drives and network shares are refused unless `self.jail_policy()` allows them.
+    /// Symlinks are followed in every component below `base`, including those met in symlink targets,
+    /// and `..` is applied before following them; the components of `base` itself are not looked at.
+    /// Native symlinks and junctions are followed too, as Windows would; those that cannot be read are refused.
+    /// Could be called without being wrapped in any checks, as `resolve_path`.
+    pub fn resolve_path_within(&self, base: &Path, path: &Path) -> Result<PathBuf, JailError> {
+        let base_native =
+            if base.starts_with("/") { self.native_path_of(base.as_os_str()) } else { PathBuf::from(base) };
+        let base_native = jail::normalize_native_path(&base_native);
+        let native =
+            if path.starts_with("/") {
+                self.native_path_of(path.as_os_str())
+            } else if is_relative_cygwin_path(path) {
+                let mut path_b = path.as_os_str().as_encoded_bytes().to_vec();
+                backslash_the_slashes_in_bytes(&mut path_b);
+                base_native.join(os_string_from_wtf8(path_b))
             } else {
-                PathBuf::from(p)
+                PathBuf::from(path)
             };
-        if !maybe_cygwin_symlink(&p_native.as_path()) { return p_native }
-        return self.resolve_symlink(&p_native.as_path())
+        let native = jail::normalize_native_path(&native);
+        if !jail::is_within(&base_native, &native) {
+            return Err(JailError::Escapes(native));
+        }
+        if !self.running_under_cygwin { return Ok(self.apply_output_style(native)) }
+        // Components below the base are resolved one at a time, and the components of each symlink target
+        // are pushed back to be resolved the same way, so that a link in a target cannot escape either
+        let base_len = base_native.components().count();
+        let base_prefix: PathBuf = native.components().take(base_len).collect();
+        // Names below the base, last first, to be popped in order
+        let names_below_base = |path: &Path| {
+            let mut names: Vec<OsString> = path.components().skip(base_len).map(|c| c.as_os_str().to_os_string()).collect();
+            names.reverse();
+            names
+        };
+        let mut pending = names_below_base(&native);
+        let mut ret = base_prefix.clone();
+        let mut hops = 0;
+        while let Some(name) = pending.pop() {
+            ret.push(name);
+            let cygwin_target = if self.could_be_symlink(&ret) { self.read_symlink_contents(&ret) } else { None };
+            let next = match cygwin_target {
+                Some(cygwin_target) => {
+                    hops += 1;
+                    if hops > self.max_symlink_hops {
+                        return Err(JailError::TooManyLinks(ret));
+                    }
+                    let target_b = cygwin_target.as_os_str().as_encoded_bytes();
+                    if jail::is_unc_target(target_b) {
+                        if !self.jail_policy.allow_unc {
+                            return Err(JailError::UncTarget(ret));
+                        }
+                    } else if (jail::is_drive_target(target_b) || self.find_cygdrive(target_b).is_some())
+                            && !self.jail_policy.allow_cygdrive {
+                        return Err(JailError::CygdriveTarget(ret));
+                    }
+                    self.join_symlink_native_path_and_cygwin_target(&ret, &cygwin_target)
+                },
+                // Native symlinks and junctions, which Windows follows by itself
+                None if is_name_surrogate(&ret) => {
+                    hops += 1;
+                    if hops > self.max_symlink_hops {
+                        return Err(JailError::TooManyLinks(ret));
+                    }
+                    let native_target = match fs::read_link(&ret) {
+                        Err(_) => return Err(JailError::UnreadableLink(ret)),
+                        Ok(target) => jail::strip_verbatim_prefix(&target),
+                    };
+                    match ret.parent() {
+                        None => native_target,
+                        Some(dir) => dir.join(native_target),
+                    }
+                },
+                None => continue,
+            };
+            let next = jail::normalize_native_path(&next);
+            if !jail::is_within(&base_native, &next) {
+                return Err(JailError::Escapes(next));
+            }
+            pending.extend(names_below_base(&next));
+            ret = base_prefix.clone();
+        }
+        Ok(self.apply_output_style(ret))
     }
 }
 
-/// Queries the file system about whether the file could be a Cygwin symlink.
+#[cfg(all(windows, feature = "std"))]
+impl SymlinkProbe for CygRoot {
+    fn maybe_symlink(&self, path: &Path) -> bool {
+        self.could_be_symlink(path)
+    }
+
+    fn read_symlink(&self, path: &Path) -> Option<PathBuf> {
+        self.read_symlink_contents(path)
+    }
+}
+
+/// Queries the file system about whether the file could be a Cygwin symlink, that is, a system file.
+/// WSL symlinks, which are reparse points, are not reported; `CygRoot::resolve_path()` tells them
+/// by their reparse tag from junctions and the like.
 /// Always false not on `cfg!(windows)`.
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 pub fn maybe_cygwin_symlink(path: &Path) -> bool {
+    file_attributes(path).is_some_and(|attr| (attr & FILE_ATTRIBUTE_SYSTEM) != 0)
+}
+
+#[cfg(all(windows, feature = "std"))]
+fn file_attributes(path: &Path) -> Option<winapi::DWORD> {
     let path_wz: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
     let attr = unsafe {
         kernel32::GetFileAttributesW(path_wz.as_ptr())
     };
     if attr == winapi::INVALID_FILE_ATTRIBUTES {
-        return false;
+        return None;
     }
-    return (attr & FILE_ATTRIBUTE_SYSTEM) != 0;
+    Some(attr)
 }
 
-// Utilites
+/// Whether `path` is a reparse point standing for another file, like a native symlink or a junction,
+/// rather than, say, a cloud file.
+#[cfg(all(windows, feature = "std"))]
+fn is_name_surrogate(path: &Path) -> bool {
+    file_attributes(path).is_some_and(|attr| (attr & FILE_ATTRIBUTE_REPARSE_POINT) != 0)
+        && reparse_tag(path).is_some_and(|tag| (tag & IO_REPARSE_TAG_NAME_SURROGATE) != 0)
+}
 
-#[cfg(windows)]
-fn find_in_pathlist(pathlist: &Option<OsString>, filename: &Path) -> Option<PathBuf> {
-    match pathlist {
-        &None => None,
-        &Some(ref pathlist_os) => {
-            for dir in std::env::split_paths(&pathlist_os) {
-                let filepath = dir.join(filename);
-                if filepath.is_file() {
-                    return Some(filepath);
-                }
-            }
-            None
-        }
+/// Returns the reparse tag of a reparse point, as listed in its directory, without opening it
+/// (which could download a cloud file).
+#[cfg(all(windows, feature = "std"))]
+fn reparse_tag(path: &Path) -> Option<winapi::DWORD> {
+    let path_wz: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+    let mut find_data: winapi::WIN32_FIND_DATAW = unsafe { mem::zeroed() };
+    let handle = unsafe {
+        kernel32::FindFirstFileW(path_wz.as_ptr(), &mut find_data)
+    };
+    if handle == winapi::INVALID_HANDLE_VALUE {
+        return None;
     }
+    unsafe {
+        kernel32::FindClose(handle);
+    }
+    if (find_data.dwFileAttributes & FILE_ATTRIBUTE_REPARSE_POINT) == 0 {
+        return None;
+    }
+    Some(find_data.dwReserved0)
 }
 
-#[cfg(windows)]
-fn valid_drive_letter(x: char) -> bool {
-    (x >= 'a' && x <= 'z') || (x >= 'A' && x <= 'Z')
+#[cfg(all(windows, feature = "std"))]
+fn is_wsl_symlink(path: &Path) -> bool {
+    reparse_tag(path) == Some(compat::IO_REPARSE_TAG_LX_SYMLINK)
 }
 
-#[cfg(windows)]
-fn pop_char<'a>(s: &'a str) -> Option<(char, &'a str)> {
-    let mut ret_beg = 0;
-    let mut ret_ch = None;
-    for (i, ch) in s.char_indices() {
-        if i == 0 {
-            ret_ch = Some(ch);
-        } else {
-            ret_beg = i;
-            break;
-        }
+/// Reads the target of a WSL symlink reparse point, which Win32 cannot open as a file.
+#[cfg(all(windows, feature = "std"))]
+fn read_wsl_symlink(path: &Path) -> Option<Vec<u8>> {
+    let path_wz: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+    let handle = unsafe {
+        kernel32::CreateFileW(path_wz.as_ptr(), 0, FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            null_mut(), winapi::OPEN_EXISTING,
+            winapi::FILE_FLAG_OPEN_REPARSE_POINT | winapi::FILE_FLAG_BACKUP_SEMANTICS, null_mut())
+    };
+    if handle == winapi::INVALID_HANDLE_VALUE {
+        return None;
     }
-    match ret_ch {
-        None => None,
-        Some(ch) => {
-            if ret_beg == 0 {
-                Some((ch, ""))
-            } else {
-                let ret_b = &s.as_bytes()[ret_beg..];
-                let ret_s = unsafe {
-                    ::std::str::from_utf8_unchecked(ret_b)
-                };
-                Some((ch, ret_s))
-            }
-        }
+    let mut reparse_data = vec![0u8; MAXIMUM_REPARSE_DATA_BUFFER_SIZE];
+    let mut reparse_data_len: winapi::DWORD = 0;
+    let ok = unsafe {
+        let ok = kernel32::DeviceIoControl(handle, winapi::FSCTL_GET_REPARSE_POINT, null_mut(), 0,
+            reparse_data.as_mut_ptr() as winapi::LPVOID, reparse_data.len() as winapi::DWORD,
+            &mut reparse_data_len, null_mut());
+        kernel32::CloseHandle(handle);
+        ok
+    };
+    if ok == 0 {
+        return None;
     }
+    compat::wsl_symlink_target(&reparse_data[..reparse_data_len as usize]).map(|target| target.to_vec())
 }
 
-#[cfg(windows)]
-fn eat_chars<'a>(s: &'a str, x: char) -> Option<&'a str> {
-    let mut ret_beg = 0;
-    for (i, ch) in s.char_indices() {
-        if ch != x {
-            ret_beg = i;
-            break;
-        }
+#[cfg(feature = "std")]
+impl Default for CygRoot {
+    fn default() -> CygRoot {
+        CygRoot::new()
     }
-    if ret_beg == 0 {
-        None
-    } else {
-        let ret_b = &s.as_bytes()[ret_beg..];
-        let ret_s = unsafe {
-            ::std::str::from_utf8_unchecked(ret_b)
-        };
-        Some(ret_s)
+}
+
+// Utilites
+
+/// Size of the largest reparse point `FSCTL_GET_REPARSE_POINT` can return.
+#[cfg(all(windows, feature = "std"))]
+const MAXIMUM_REPARSE_DATA_BUFFER_SIZE: usize = 16 * 1024;
+
+/// The bit of reparse tags of reparse points that stand for another file, as symlinks and junctions do.
+#[cfg(all(windows, feature = "std"))]
+const IO_REPARSE_TAG_NAME_SURROGATE: winapi::DWORD = 0x2000_0000;
+
+/// Environment variables `CygRoot::from_env()` keeps, as they are looked up after construction.
+#[cfg(all(windows, feature = "std"))]
+const ENV_VARS: &[&str] = &[
+    "PWD", "HOME", "USERNAME", "USERDOMAIN", "USERPROFILE",
+    "PUBLIC", "ALLUSERSPROFILE", "ProgramData", "APPDATA", "LOCALAPPDATA",
+    "SYSTEMROOT", "WINDIR", "ProgramFiles", "CommonProgramFiles",
+    "LC_ALL", "LC_CTYPE", "LANG",
+];
+
+/// Whether a path is relative both for Cygwin and Windows,
+/// that is, has neither a root nor a drive.
+#[cfg(all(windows, feature = "std"))]
+fn is_relative_cygwin_path(path: &Path) -> bool {
+    match path.components().next() {
+        None => false,
+        Some(Component::Prefix(_)) | Some(Component::RootDir) => false,
+        Some(_) => true,
     }
 }
 
-#[cfg(windows)]
-fn eat_str<'a>(s: &'a str, s1: &str) -> Option<&'a str> {
-    let mut chs1 = s1.chars();
-    let mut ret_beg = 0;
-    for (i, ch) in s.char_indices() {
-        match chs1.next() {
-            None => {
-                ret_beg = i;
-                break;
-            },
-            Some(x) => {
-                if ch != x {
-                    return None;
-                }
-            },
-        }
+/// Removes `.`, `..` and repeated slashes from an absolute Cygwin path, without looking at the file system.
+/// `/..` is `/`, as in Cygwin.
+#[cfg(all(windows, feature = "std"))]
+fn normalize_posix_path(path: &str) -> String {
+    let ret = grammar::normalize_posix_path(path.as_bytes());
+    unsafe {
+        String::from_utf8_unchecked(ret)
     }
-    if chs1.next().is_some() {
-        None
-    } else {
-        let ret_b = &s.as_bytes()[ret_beg..];
-        let ret_s = unsafe {
-            ::std::str::from_utf8_unchecked(ret_b)
-        };
-        Some(ret_s)
-    }
-}
-
-#[cfg(windows)]
-fn ascii_upcase(x: char) -> char {
-    if x >= 'a' && x <= 'z' {
-        let c = x as u32;
-        let d = c - ('a' as u32);
-        return ::std::char::from_u32(('A' as u32) + d).unwrap_or('\0');
-    }
-    return x;
-}
-
-#[cfg(windows)]
-fn string_from_utf_bom_lossy(data: &[u8]) -> String {
-    let byte_order_mark = &data[..2];
-    let byte_order_mark_islen = byte_order_mark.len() == 2;
-    let data16_is_big_endian =
-        byte_order_mark_islen && byte_order_mark[0] == 254 && byte_order_mark[1] == 255;
-    let data16_is_little_endian =
-        byte_order_mark_islen && byte_order_mark[0] == 255 && byte_order_mark[1] == 254;
-
-    if !data16_is_big_endian && !data16_is_little_endian {
-        return String::from_utf8_lossy(data).into_owned();
-    } else {
-        let data_nobom16 = &data[2..];
-        let mut codepoints_in_data = Vec::<u16>::with_capacity(data_nobom16.len() / 2);
-        let mut even_byte = true;
-        let mut codepoint: u16 = 0;
-        for (i, b) in data_nobom16.iter().enumerate() {
-            let upper_byte =
-                if data16_is_big_endian { even_byte } else { !even_byte };
-            let b16 = *b as u16;
-            codepoint +=
-                if upper_byte { b16 * 256 } else { b16 };
-            if !even_byte {
-                if codepoint == 0 && i != 0 { break }
-                codepoints_in_data.push(codepoint);
-                codepoint = 0;
-            }
-            even_byte = !even_byte;
+}
+
+/// Joins a path relative to the Cygwin working directory to it, applying `..`.
+#[cfg(all(windows, feature = "std"))]
+fn join_cygwin_cwd(cwd: &str, relative_path: &OsStr) -> OsString {
+    let relative_path = relative_path.as_encoded_bytes();
+    let mut cygwin_path = Vec::<u8>::with_capacity(cwd.len() + 1 + relative_path.len());
+    cygwin_path.extend_from_slice(cwd.as_bytes());
+    cygwin_path.push(b'/');
+    cygwin_path.extend_from_slice(relative_path);
+    os_string_from_wtf8(grammar::normalize_posix_path(&cygwin_path))
+}
+
+#[cfg(all(windows, feature = "std"))]
+fn slash_the_backslashes_in_string(s: &mut String) {
+    unsafe {
+        slash_the_backslashes_in_bytes(s.as_mut_vec());
+    }
+}
+
+#[cfg(all(windows, feature = "std"))]
+fn slash_the_backslashes_in_bytes(v: &mut [u8]) {
+    for b in v.iter_mut() {
+        if *b == b'\\' {
+            *b = b'/';
         }
-        return String::from_utf16_lossy(codepoints_in_data.as_slice());
     }
 }
 
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 fn backslash_the_slashes_in_string(s: &mut String) {
     unsafe {
-        let v = s.as_mut_vec();
-        for mut b in v.iter_mut() {
-            if *b == b'/' {
-                *b = b'\\';
-            }
+        backslash_the_slashes_in_bytes(s.as_mut_vec());
+    }
+}
+
+#[cfg(all(windows, feature = "std"))]
+fn backslash_the_slashes_in_bytes(v: &mut [u8]) {
+    for b in v.iter_mut() {
+        if *b == b'/' {
+            *b = b'\\';
         }
     }
 }
 
+/// Appends a component to a native path being written, after a backslash unless the path is empty or ends in one.
+#[cfg(all(windows, feature = "std"))]
+fn push_native_component(out: &mut Vec<u8>, component: &[u8]) {
+    if !out.is_empty() && !out.ends_with(b"\\") && !out.ends_with(b"/") {
+        out.push(b'\\');
+    }
+    out.extend_from_slice(component);
+}
+
+/// Returns the length of the native directory `dir` if the slashed `path` is it or is under it,
+/// comparing case-insensitively, as Windows does, and ignoring trailing slashes of `dir`.
+#[cfg(all(windows, feature = "std"))]
+fn native_prefix_len(path: &[u8], dir: &Path) -> Option<usize> {
+    let dir_b = dir.as_os_str().as_encoded_bytes();
+    let dir_len = dir_b.iter().rposition(|&b| b != b'/' && b != b'\\').map_or(0, |i| i + 1);
+    if dir_len == 0 || path.len() < dir_len {
+        return None;
+    }
+    let same = path[..dir_len].iter().zip(dir_b[..dir_len].iter())
+        .all(|(&p, &d)| p.eq_ignore_ascii_case(&if d == b'\\' { b'/' } else { d }));
+    if same && path_is_under(path, &path[..dir_len]) { Some(dir_len) } else { None }
+}
+
+/// Makes an `OsString` from bytes of `OsStr::as_encoded_bytes()`, or of the UTF-8 parts spliced into them.
+#[cfg(all(windows, feature = "std"))]
+fn os_string_from_wtf8(data: Vec<u8>) -> OsString {
+    unsafe {
+        OsString::from_encoded_bytes_unchecked(data)
+    }
+}
+
 #[cfg(test)]
-#[cfg(windows)]
+#[cfg(all(windows, feature = "std"))]
 mod win32_tests {
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use CygRoot;
-use string_from_utf_bom_lossy;
+use crate::CygRoot;
+use crate::decode_symlink_target;
+use crate::normalize_posix_path;
 
 fn cygwin() -> CygRoot {
     let root = PathBuf::from("F:\\cygwin");
-    return CygRoot {
-        running_under_cygwin: true,
-        native_path_to_root: root,
+    CygRoot::from(root, true)
+}
+
+/// Writes a Cygwin symlink: a system file with the magic and a UTF-16 target.
+fn write_cygwin_symlink(path: &Path, target: &str) {
+    use std::os::windows::ffi::OsStrExt;
+    let mut data = b"!<symlink>\xff\xfe".to_vec();
+    for unit in target.encode_utf16().chain(Some(0)) {
+        data.extend_from_slice(&[unit as u8, (unit >> 8) as u8]);
+    }
+    std::fs::write(path, &data).unwrap();
+    let path_wz: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let ok = unsafe {
+        kernel32::SetFileAttributesW(path_wz.as_ptr(), winapi::winnt::FILE_ATTRIBUTE_SYSTEM)
     };
+    assert!(ok != 0);
+}
+
+/// Makes an empty directory for a test to be a Cygwin root.
+fn temp_root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("cygwin_fs-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    root
 }
 
 #[test]
 fn converts_absolute_posix_paths() {
     let cygroot = cygwin();
     let posix = String::from("/tmp");
-    let win32_p = cygroot.convert_path_to_native(&posix.as_str());
+    let win32_p = cygroot.convert_path_to_native(posix.as_str());
     let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
     assert_eq!(win32_s, "F:\\cygwin\\tmp");
 }
@@ -531,7 +1547,7 @@ fn converts_absolute_posix_paths() {
 fn converts_absolute_posix_paths_several_levels_deep() {
     let cygroot = cygwin();
     let posix = String::from("/tmp/abc/def/ghi");
-    let win32_p = cygroot.convert_path_to_native(&posix.as_str());
+    let win32_p = cygroot.convert_path_to_native(posix.as_str());
     let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
     assert_eq!(win32_s, "F:\\cygwin\\tmp\\abc\\def\\ghi");
 }
@@ -540,7 +1556,7 @@ fn converts_absolute_posix_paths_several_levels_deep() {
 fn converts_absolute_posix_dirs_several_levels_deep() {
     let cygroot = cygwin();
     let posix = String::from("/tmp/abc/def/ghi/");
-    let win32_p = cygroot.convert_path_to_native(&posix.as_str());
+    let win32_p = cygroot.convert_path_to_native(posix.as_str());
     let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
     assert_eq!(win32_s, "F:\\cygwin\\tmp\\abc\\def\\ghi");
 }
@@ -549,7 +1565,7 @@ fn converts_absolute_posix_dirs_several_levels_deep() {
 fn converts_absolute_cygdrive_paths() {
     let cygroot = cygwin();
     let posix = String::from("/cygdrive/f");
-    let win32_p = cygroot.convert_path_to_native(&posix.as_str());
+    let win32_p = cygroot.convert_path_to_native(posix.as_str());
     let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
     assert_eq!(win32_s, "F:\\");
 }
@@ -558,7 +1574,7 @@ fn converts_absolute_cygdrive_paths() {
 fn converts_absolute_cygdrive_paths_several_levels_deep() {
     let cygroot = cygwin();
     let posix = String::from("/cygdrive/f/a/bb/ccc");
-    let win32_p = cygroot.convert_path_to_native(&posix.as_str());
+    let win32_p = cygroot.convert_path_to_native(posix.as_str());
     let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
     assert_eq!(win32_s, "F:\\a\\bb\\ccc");
 }
@@ -567,7 +1583,7 @@ fn converts_absolute_cygdrive_paths_several_levels_deep() {
 fn converts_absolute_cygdrive_dirs_several_levels_deep() {
     let cygroot = cygwin();
     let posix = String::from("/cygdrive/f/a/bb/ccc/");
-    let win32_p = cygroot.convert_path_to_native(&posix.as_str());
+    let win32_p = cygroot.convert_path_to_native(posix.as_str());
     let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
     assert_eq!(win32_s, "F:\\a\\bb\\ccc");
 }
@@ -575,21 +1591,21 @@ fn converts_absolute_cygdrive_dirs_several_levels_deep() {
 #[test]
 fn reads_utf16le() {
     let data : Vec<u8> = vec![ 0xff, 0xfe, b'a', 0, b'b', 0 ];
-    let s = string_from_utf_bom_lossy(data.as_slice());
+    let s = decode_symlink_target(data.as_slice(), crate::Codepage::Utf8).unwrap();
     assert_eq!(s, "ab");
 }
 
 #[test]
 fn reads_utf16be() {
     let data : Vec<u8> = vec![ 0xfe, 0xff, 0, b'a', 0, b'b' ];
-    let s = string_from_utf_bom_lossy(data.as_slice());
+    let s = decode_symlink_target(data.as_slice(), crate::Codepage::Utf8).unwrap();
     assert_eq!(s, "ab");
 }
 
 #[test]
 fn reads_utf8_when_no_utf16bom() {
     let data : Vec<u8> = vec![ b'a', b'b' ];
-    let s = string_from_utf_bom_lossy(data.as_slice());
+    let s = decode_symlink_target(data.as_slice(), crate::Codepage::Utf8).unwrap();
     assert_eq!(s, "ab");
 }
 
@@ -643,4 +1659,338 @@ fn joins_symlink_empty_native_path_and_cygwin_relative_target() {
     assert_eq!(win32_s, "a\\bb\\ccc");
 }
 
+#[test]
+fn normalizes_posix_paths() {
+    assert_eq!(normalize_posix_path("/home//user/./src/"), "/home/user/src");
+    assert_eq!(normalize_posix_path("/home/user/../other/x"), "/home/other/x");
+    assert_eq!(normalize_posix_path("/.."), "/");
+    assert_eq!(normalize_posix_path("/"), "/");
+}
+
+#[test]
+fn resolves_relative_paths_against_cwd() {
+    let mut cygroot = cygwin();
+    cygroot.set_cwd(Some("/home/user/src"));
+    let win32_p = cygroot.resolve_path(PathBuf::from("../build/out").as_path());
+    let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
+    assert_eq!(win32_s, "F:\\cygwin\\home\\user\\build\\out");
+}
+
+#[test]
+fn keeps_relative_paths_without_cwd() {
+    let cygroot = cygwin();
+    let win32_p = cygroot.resolve_path(PathBuf::from("build\\out").as_path());
+    let win32_s = win32_p.as_os_str().to_string_lossy().into_owned();
+    assert_eq!(win32_s, "build\\out");
+}
+
+#[test]
+fn converts_native_paths_under_root_to_posix() {
+    let cygroot = cygwin();
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("F:\\cygwin\\home\\user").as_path()), "/home/user");
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("f:\\CYGWIN\\").as_path()), "/");
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("F:\\cygwin64\\bin").as_path()), "/cygdrive/f/cygwin64/bin");
+}
+
+#[test]
+fn converts_native_drive_and_unc_paths_to_posix() {
+    let cygroot = cygwin();
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("C:\\Users\\user\\").as_path()), "/cygdrive/c/Users/user");
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("D:\\").as_path()), "/cygdrive/d");
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("\\\\server\\share\\x").as_path()), "//server/share/x");
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("a\\b").as_path()), "a/b");
+}
+
+#[test]
+fn looks_up_variables_in_given_env() {
+    let mut env = ::std::collections::HashMap::new();
+    env.insert(String::from("HOME"), ::std::ffi::OsString::from("/home/other"));
+    env.insert(String::from("PWD"), ::std::ffi::OsString::from("/tmp"));
+    let mut cygroot = CygRoot::from_env(&env);
+    cygroot.set_cwd_from_pwd();
+    assert_eq!(cygroot.home_dir(None), Some(String::from("/home/other")));
+    assert_eq!(cygroot.cwd(), Some("/tmp"));
+}
+
+#[test]
+fn converts_with_compat_profile() {
+    let mut cygroot = cygwin();
+    assert_eq!(cygroot.convert_path_to_native("/usr/bin/ls"), PathBuf::from("F:\\cygwin\\bin\\ls"));
+    assert_eq!(cygroot.convert_path_to_native("/usr/binx"), PathBuf::from("F:\\cygwin\\usr\\binx"));
+    assert_eq!(cygroot.convert_path_to_native("/tmp/a:b"), PathBuf::from("F:\\cygwin\\tmp\\a\u{f03a}b"));
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("F:\\cygwin\\tmp\\a\u{f03a}b").as_path()), "/tmp/a:b");
+    let profile = crate::CompatProfile { cygdrive_prefix: String::from("/"), ..Default::default() };
+    cygroot.set_compat_profile(Some(profile));
+    assert_eq!(cygroot.convert_path_to_native("/c/x"), PathBuf::from("C:\\x"));
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("C:\\x").as_path()), "/c/x");
+}
+
+#[test]
+fn reads_codepage_when_no_utf16bom() {
+    let data : Vec<u8> = vec![ b'/', 0xcf, 0xf0, 0xe8 ];
+    assert_eq!(decode_symlink_target(data.as_slice(), crate::Codepage::Windows1251).unwrap(), "/При");
+    let data : Vec<u8> = vec![ 0xff, 0xfe, b'a', 0 ];
+    assert_eq!(decode_symlink_target(data.as_slice(), crate::Codepage::Windows1251).unwrap(), "a");
+}
+
+#[test]
+fn converts_short_and_trailing_components() {
+    let cygroot = cygwin();
+    assert_eq!(cygroot.convert_path_to_native("/tmp/a"), PathBuf::from("F:\\cygwin\\tmp\\a"));
+    assert_eq!(cygroot.convert_path_to_native("a/b"), PathBuf::from("a\\b"));
+    assert_eq!(cygroot.convert_path_to_native("/cygdrive/c/"), PathBuf::from("C:\\"));
+    assert_eq!(cygroot.convert_path_to_native("/cygdrive/cc"), PathBuf::from("F:\\cygwin\\cygdrive\\cc"));
+}
+
+#[test]
+fn converts_paths_with_unpaired_surrogates() {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    let cygroot = cygwin();
+    let mut posix: Vec<u16> = "/tmp/a".encode_utf16().collect();
+    posix.push(0xd800);
+    let native = cygroot.convert_path_to_native_os(OsString::from_wide(&posix));
+    let mut expected: Vec<u16> = "F:\\cygwin\\tmp\\a".encode_utf16().collect();
+    expected.push(0xd800);
+    assert_eq!(native.as_os_str().encode_wide().collect::<Vec<u16>>(), expected);
+    assert_eq!(cygroot.convert_path_to_cygwin_os(native.as_path()), OsString::from_wide(&posix));
+    assert_eq!(cygroot.resolve_path(PathBuf::from(OsString::from_wide(&posix)).as_path()), native);
+}
+
+#[test]
+fn converts_byte_paths_in_locale_charset() {
+    let mut env = ::std::collections::HashMap::new();
+    env.insert(String::from("LANG"), ::std::ffi::OsString::from("ru_RU.KOI8-R"));
+    let mut cygroot = cygwin();
+    cygroot.set_charset(Some(crate::Charset::from_env(&env)));
+    assert_eq!(cygroot.convert_bytes_to_native(b"/tmp/\xf0\xd2\xc9"), PathBuf::from("F:\\cygwin\\tmp\\\u{41f}\u{440}\u{438}"));
+    assert_eq!(cygroot.convert_path_to_cygwin_bytes(PathBuf::from("F:\\cygwin\\tmp\\\u{41f}\u{440}\u{438}").as_path()),
+        b"/tmp/\xf0\xd2\xc9".to_vec());
+    assert_eq!(cygroot.convert_bytes_to_native(b"/tmp/\xff"), PathBuf::from("F:\\cygwin\\tmp\\\u{42a}"));
+    cygroot.set_charset(Some(crate::Charset::utf8()));
+    assert_eq!(cygroot.convert_bytes_to_native(b"/tmp/\xff"), PathBuf::from("F:\\cygwin\\tmp\\\u{f0ff}"));
+}
+
+#[test]
+fn resolves_paths_within_base() {
+    let cygroot = cygwin();
+    let base = PathBuf::from("/home/build");
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("src/../x").as_path()),
+        Ok(PathBuf::from("F:\\cygwin\\home\\build\\x")));
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("/home/build/x").as_path()),
+        Ok(PathBuf::from("F:\\cygwin\\home\\build\\x")));
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("../../etc").as_path()),
+        Err(crate::JailError::Escapes(PathBuf::from("F:\\cygwin\\etc"))));
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("/cygdrive/c/Windows").as_path()),
+        Err(crate::JailError::Escapes(PathBuf::from("C:\\Windows"))));
+}
+
+#[test]
+fn resolves_with_symlink_policy() {
+    let mut cygroot = cygwin();
+    cygroot.set_cwd(Some("/home/user"));
+    for &policy in [crate::SymlinkPolicy::Never, crate::SymlinkPolicy::FinalOnce, crate::SymlinkPolicy::FinalOnly,
+            crate::SymlinkPolicy::AllComponents].iter() {
+        assert_eq!(cygroot.resolve_path_with_policy(PathBuf::from("/tmp/x").as_path(), policy),
+            PathBuf::from("F:\\cygwin\\tmp\\x"));
+        assert_eq!(cygroot.resolve_path_with_policy(PathBuf::from("../x").as_path(), policy),
+            PathBuf::from("F:\\cygwin\\home\\x"));
+    }
+}
+
+#[test]
+fn builds_with_mounts_and_cygdrive_prefix() {
+    let cygroot = crate::CygRootBuilder::new()
+        .root(PathBuf::from("F:\\cygwin"))
+        .mount("/data", PathBuf::from("D:\\data"))
+        .cygdrive_prefix("/mnt")
+        .output_style(crate::PathStyle::Mixed)
+        .build()
+        .unwrap();
+    assert_eq!(cygroot.convert_path_to_native("/data/x"), PathBuf::from("D:/data/x"));
+    assert_eq!(cygroot.convert_path_to_native("/mnt/c/x"), PathBuf::from("C:/x"));
+    assert_eq!(cygroot.convert_path_to_native("/tmp"), PathBuf::from("F:/cygwin/tmp"));
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("D:\\data\\x").as_path()), "/data/x");
+    assert_eq!(cygroot.convert_path_to_cygwin(PathBuf::from("C:\\x").as_path()), "/mnt/c/x");
+}
+
+#[test]
+fn converts_with_longest_mount_point() {
+    let mut cygroot = cygwin();
+    cygroot.add_mount("/usr", PathBuf::from("D:\\usr"));
+    assert_eq!(cygroot.convert_path_to_native("/usr/bin/x"), PathBuf::from("F:\\cygwin\\bin\\x"));
+    assert_eq!(cygroot.convert_path_to_native("/usr/share/x"), PathBuf::from("D:\\usr\\share\\x"));
+    cygroot.add_mount("/usr/lib", PathBuf::from("D:\\lib"));
+    assert_eq!(cygroot.convert_path_to_native("/usr/lib/x"), PathBuf::from("D:\\lib\\x"));
+}
+
+#[test]
+fn traces_resolution_steps() {
+    use crate::ResolutionStep;
+    let mut cygroot = CygRoot::from(PathBuf::from("F:\\cygwin"), true);
+    cygroot.add_mount("/data", PathBuf::from("F:\\no such data"));
+    let native = PathBuf::from("F:\\no such data\\x");
+    let (resolved, steps) = cygroot.resolve_path_traced(PathBuf::from("/data/x").as_path());
+    assert_eq!(resolved, native);
+    assert_eq!(steps, vec![
+        ResolutionStep::Policy(crate::SymlinkPolicy::FinalOnly),
+        ResolutionStep::Mount { mount_point: String::from("/data"), native: PathBuf::from("F:\\no such data") },
+        ResolutionStep::Converted(native.clone()),
+        ResolutionStep::NotASymlink(native.clone()),
+        ResolutionStep::Resolved(native.clone()),
+    ]);
+}
+
+#[test]
+fn resolves_through_cache() {
+    let mut cygroot = cygwin();
+    cygroot.set_cwd(Some("/home/user"));
+    let mut resolver = crate::CachingResolver::new(&cygroot, 16);
+    for &path in ["/tmp/x", "../x", "/tmp/x"].iter() {
+        assert_eq!(resolver.resolve_path(PathBuf::from(path).as_path()),
+            resolver.cygroot().resolve_path(PathBuf::from(path).as_path()));
+    }
+    assert!(resolver.is_empty());
+    resolver.set_validate_mtime(false);
+    resolver.resolve_path(PathBuf::from("/tmp/x").as_path());
+    assert_eq!(resolver.len(), 1);
+    resolver.clear();
+    assert!(resolver.is_empty());
+}
+
+#[test]
+fn resolves_lists_of_paths() {
+    let mut cygroot = cygwin();
+    cygroot.set_cwd(Some("/home/user"));
+    cygroot.set_symlink_policy(crate::SymlinkPolicy::AllComponents);
+    let paths = ["/tmp/x", "../x", "/cygdrive/c/x", "/tmp/x"];
+    let resolved = cygroot.resolve_paths(paths.iter());
+    assert_eq!(resolved.len(), paths.len());
+    for (path, resolved) in paths.iter().zip(resolved.iter()) {
+        assert_eq!(resolved, &cygroot.resolve_path(PathBuf::from(path).as_path()));
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn resolves_without_blocking() {
+    let mut cygroot = cygwin();
+    cygroot.set_cwd(Some("/home/user"));
+    fn assert_send<T: Send>(_: &T) {}
+    assert_send(&cygroot.resolve_path_async(PathBuf::from("/tmp").as_path()));
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    for &policy in [crate::SymlinkPolicy::Never, crate::SymlinkPolicy::FinalOnce, crate::SymlinkPolicy::AllComponents].iter() {
+        for &path in ["/tmp/x", "../x", "/cygdrive/c/x"].iter() {
+            let path = PathBuf::from(path);
+            assert_eq!(runtime.block_on(cygroot.resolve_path_with_policy_async(path.as_path(), policy)),
+                cygroot.resolve_path_with_policy(path.as_path(), policy));
+        }
+    }
+}
+
+#[test]
+fn converts_into_buffers_and_borrows() {
+    use std::borrow::Cow;
+    use std::ffi::OsString;
+    let mut cygroot = cygwin();
+    cygroot.add_mount("/data", PathBuf::from("D:\\data\\"));
+    let mut native = OsString::from("left over");
+    for &path in ["/tmp/x", "/data/x", "/cygdrive/c/x/", "/usr/bin/a:b", "a/b", ""].iter() {
+        cygroot.convert_path_to_native_into(path, &mut native);
+        assert_eq!(PathBuf::from(&native), cygroot.convert_path_to_native(path));
+    }
+    let mut cygwin_path = String::from("left over");
+    for &path in ["F:\\cygwin\\tmp\\", "D:\\Data\\x", "C:\\", "C:\\x\\\\", "\\\\?\\C:\\x", "a\\b", ""].iter() {
+        cygroot.convert_path_to_cygwin_into(PathBuf::from(path).as_path(), &mut cygwin_path);
+        assert_eq!(cygwin_path, cygroot.convert_path_to_cygwin(PathBuf::from(path).as_path()));
+        assert_eq!(cygroot.convert_path_to_cygwin_cow(PathBuf::from(path).as_path()),
+            cygroot.convert_path_to_cygwin_os(PathBuf::from(path).as_path()));
+    }
+    let relative = PathBuf::from("src/main.rs");
+    assert!(matches!(cygroot.convert_path_to_cygwin_cow(relative.as_path()), Cow::Borrowed(_)));
+    assert!(matches!(cygroot.convert_path_to_cygwin_cow(PathBuf::from("src\\main.rs").as_path()), Cow::Owned(_)));
+    let native_path = PathBuf::from("F:\\no such dir\\x");
+    assert!(matches!(cygroot.resolve_path_cow(native_path.as_path()), Cow::Borrowed(_)));
+    assert_eq!(cygroot.resolve_path_cow(PathBuf::from("/tmp").as_path()), cygroot.resolve_path(PathBuf::from("/tmp").as_path()));
+}
+
+#[test]
+fn reports_undecodable_symlink_targets() {
+    let cygroot = cygwin();
+    let dir = std::env::temp_dir().join(format!("cygwin_fs-decode-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let utf16 = dir.join("utf16");
+    std::fs::write(&utf16, b"!<symlink>\xff\xfe/\0t\0\0\0").unwrap();
+    assert_eq!(cygroot.try_read_symlink_contents(&utf16), Ok(Some(PathBuf::from("/t"))));
+    let malformed = dir.join("malformed");
+    std::fs::write(&malformed, b"!<symlink>a\xffb").unwrap();
+    assert_eq!(cygroot.try_read_symlink_contents(&malformed), Err(crate::DecodeError::Malformed(crate::Codepage::Utf8)));
+    assert_eq!(cygroot.read_symlink_contents(&malformed), None);
+    let plain = dir.join("plain");
+    std::fs::write(&plain, b"not a symlink").unwrap();
+    assert_eq!(cygroot.try_read_symlink_contents(&plain), Ok(None));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn follows_junctions_only_within_base() {
+    use std::process::Command;
+    let dir = std::env::temp_dir().join(format!("cygwin_fs-junction-{}", std::process::id()));
+    let base = dir.join("base");
+    let inner = base.join("inner");
+    let outside = dir.join("outside");
+    std::fs::create_dir_all(&inner).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    for &(link, target) in [("in", &inner), ("out", &outside)].iter() {
+        let status = Command::new("cmd").arg("/C").arg("mklink").arg("/J").arg(base.join(link)).arg(target).status().unwrap();
+        assert!(status.success());
+    }
+    let cygroot = CygRoot::from(dir.clone(), true);
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("in/x").as_path()), Ok(inner.join("x")));
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("out/x").as_path()),
+        Err(crate::JailError::Escapes(outside.clone())));
+    // Links met in symlink targets are followed and checked as well
+    write_cygwin_symlink(&base.join("x"), "out/secret");
+    write_cygwin_symlink(&base.join("hop"), "in");
+    write_cygwin_symlink(&base.join("y"), "hop/x");
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("x").as_path()),
+        Err(crate::JailError::Escapes(outside.clone())));
+    assert_eq!(cygroot.resolve_path_within(&base, PathBuf::from("y").as_path()), Ok(inner.join("x")));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn tells_symlink_policies_apart() {
+    use crate::SymlinkPolicy;
+    let root = temp_root("policy");
+    std::fs::create_dir_all(root.join("real")).unwrap();
+    std::fs::write(root.join("real").join("file"), b"").unwrap();
+    write_cygwin_symlink(&root.join("dir_link"), "real");
+    write_cygwin_symlink(&root.join("a"), "b");
+    write_cygwin_symlink(&root.join("b"), "real/file");
+    let cygroot = CygRoot::from(root.clone(), true);
+    let resolve = |path: &str, policy| cygroot.resolve_path_with_policy(Path::new(path), policy);
+    assert_eq!(resolve("/dir_link/file", SymlinkPolicy::Never), root.join("dir_link").join("file"));
+    assert_eq!(resolve("/dir_link/file", SymlinkPolicy::FinalOnly), root.join("dir_link").join("file"));
+    assert_eq!(resolve("/dir_link/file", SymlinkPolicy::FinalOnce), root.join("dir_link").join("file"));
+    assert_eq!(resolve("/dir_link/file", SymlinkPolicy::AllComponents), root.join("real").join("file"));
+    assert_eq!(resolve("/a", SymlinkPolicy::Never), root.join("a"));
+    assert_eq!(resolve("/a", SymlinkPolicy::FinalOnce), root.join("b"));
+    assert_eq!(resolve("/a", SymlinkPolicy::FinalOnly), root.join("real").join("file"));
+    assert_eq!(resolve("/a", SymlinkPolicy::AllComponents), root.join("real").join("file"));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn joins_each_symlink_hop_to_the_previous_one() {
+    let root = temp_root("hops");
+    std::fs::create_dir_all(root.join("dir").join("sub")).unwrap();
+    write_cygwin_symlink(&root.join("dir").join("first"), "sub/second");
+    write_cygwin_symlink(&root.join("dir").join("sub").join("second"), "target");
+    let cygroot = CygRoot::from(root.clone(), true);
+    assert_eq!(cygroot.resolve_symlink(&root.join("dir").join("first")),
+        root.join("dir").join("sub").join("target"));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
 }
@@ -0,0 +1,596 @@
+//! Reading of Portable Executable (`.exe` and `.dll`) headers and resources, without loading the file.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Why a file could not be read as a Portable Executable.
+#[derive(Debug)]
+pub enum PeError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file has no `MZ` or `PE` signature.
+    NotPe,
+    /// A header or table points outside of the file.
+    Truncated,
+    /// The file has no `VERSIONINFO` resource, or it has no fixed file info.
+    NoVersionInfo,
+}
+
+impl fmt::Display for PeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PeError::Io(ref err) => write!(f, "could not read the file: {}", err),
+            PeError::NotPe => write!(f, "not a PE file"),
+            PeError::Truncated => write!(f, "truncated or malformed PE file"),
+            PeError::NoVersionInfo => write!(f, "no version resource"),
+        }
+    }
+}
+
+impl Error for PeError {}
+
+impl From<io::Error> for PeError {
+    fn from(err: io::Error) -> PeError {
+        PeError::Io(err)
+    }
+}
+
+/// The processor architecture a PE file is built for.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum Machine {
+    /// 32-bit x86 (`IMAGE_FILE_MACHINE_I386`).
+    X86,
+    /// 64-bit x86 (`IMAGE_FILE_MACHINE_AMD64`).
+    X64,
+    /// 64-bit ARM (`IMAGE_FILE_MACHINE_ARM64`).
+    Arm64,
+    /// Any other `IMAGE_FILE_MACHINE_*` value.
+    Other(u16),
+}
+
+impl Machine {
+    fn from_u16(machine: u16) -> Machine {
+        match machine {
+            0x014c => Machine::X86,
+            0x8664 => Machine::X64,
+            0xaa64 => Machine::Arm64,
+            other => Machine::Other(other),
+        }
+    }
+
+    /// Whether the architecture is a 64-bit one.
+    pub fn is_64bit(&self) -> bool {
+        matches!(*self, Machine::X64 | Machine::Arm64)
+    }
+}
+
+/// A four-part version, as in `VS_FIXEDFILEINFO`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct FileVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+    pub build: u16,
+}
+
+impl FileVersion {
+    pub fn new(major: u16, minor: u16, patch: u16, build: u16) -> FileVersion {
+        FileVersion { major, minor, patch, build }
+    }
+}
+
+impl fmt::Display for FileVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.patch, self.build)
+    }
+}
+
+/// What `cygwin1.dll` says about the Cygwin runtime, as returned by `CygRoot::runtime_info()`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub struct RuntimeInfo {
+    /// The architecture, telling 32-bit Cygwin from 64-bit one.
+    pub machine: Machine,
+    /// The file version from the `VERSIONINFO` resource, like `3.4.9.0`.
+    pub version: FileVersion,
+}
+
+/// The runtime a program is linked against, telling whether it expects POSIX paths.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum Flavor {
+    /// Imports `cygwin1.dll`.
+    Cygwin,
+    /// Imports `msys-2.0.dll`, the MSYS2 fork of Cygwin.
+    Msys,
+}
+
+impl Flavor {
+    /// Returns the name of the runtime DLL, like `cygwin1.dll`.
+    pub fn dll_name(&self) -> &'static str {
+        match *self {
+            Flavor::Cygwin => "cygwin1.dll",
+            Flavor::Msys => "msys-2.0.dll",
+        }
+    }
+}
+
+/// The Windows subsystem a program runs in.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum Subsystem {
+    /// Has a console (`IMAGE_SUBSYSTEM_WINDOWS_CUI`).
+    Console,
+    /// Has no console (`IMAGE_SUBSYSTEM_WINDOWS_GUI`).
+    Gui,
+    /// Any other `IMAGE_SUBSYSTEM_*` value, as of drivers.
+    Other(u16),
+}
+
+impl Subsystem {
+    fn from_u16(subsystem: u16) -> Subsystem {
+        match subsystem {
+            2 => Subsystem::Gui,
+            3 => Subsystem::Console,
+            other => Subsystem::Other(other),
+        }
+    }
+}
+
+/// What the headers of a program say about how to run it.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub struct ExecutableInfo {
+    pub machine: Machine,
+    pub subsystem: Subsystem,
+    /// The Cygwin-like runtime the program imports, if any.
+    pub flavor: Option<Flavor>,
+    /// Names of the imported DLLs, as written in the file.
+    pub imported_dlls: Vec<String>,
+}
+
+/// Tells whether a program is a Cygwin or MSYS one (so should be given POSIX paths),
+/// by the DLLs it imports. Returns `None` for native programs, and for unreadable files.
+pub fn is_cygwin_executable(path: &Path) -> Option<Flavor> {
+    read_executable_info(path).ok().and_then(|info| info.flavor)
+}
+
+/// Reads the architecture, subsystem and imports of a program.
+/// Only the headers and the import table are read, not the whole file.
+pub fn read_executable_info(path: &Path) -> Result<ExecutableInfo, PeError> {
+    let mut file = File::open(path)?;
+    let mut headers = Vec::new();
+    (&mut file).take(MAX_HEADERS_SIZE).read_to_end(&mut headers)?;
+    let image = PeImage::parse(&headers)?;
+    executable_info(&image, &mut |offset, len| read_file_at(&mut file, offset, len))
+}
+
+/// Parses the architecture, subsystem and imports out of the contents of a program.
+pub fn parse_executable_info(data: &[u8]) -> Result<ExecutableInfo, PeError> {
+    let image = PeImage::parse(data)?;
+    executable_info(&image, &mut |offset, len| Ok(read_slice_at(data, offset, len)))
+}
+
+/// Looks up the imports of `image`, reading the parts of the file outside of the headers with `read_at`.
+fn executable_info(image: &PeImage, read_at: &mut dyn FnMut(usize, usize) -> io::Result<Vec<u8>>)
+        -> Result<ExecutableInfo, PeError> {
+    let mut imported_dlls = Vec::new();
+    if let Some((imports_offset, imports_size)) = image.data_directory(IMAGE_DIRECTORY_ENTRY_IMPORT) {
+        let imports = read_at(imports_offset, imports_size.min(MAX_IMPORTS_SIZE))?;
+        // IMAGE_IMPORT_DESCRIPTORs, terminated by a zeroed one.
+        for descriptor in imports.chunks(20) {
+            if descriptor.len() < 20 || descriptor.iter().all(|&b| b == 0) {
+                break;
+            }
+            let name_rva = read_u32(descriptor, 12).ok_or(PeError::Truncated)?;
+            let name_offset = image.rva_to_offset(name_rva).ok_or(PeError::Truncated)?;
+            let name = read_at(name_offset, MAX_DLL_NAME_SIZE)?;
+            let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            imported_dlls.push(String::from_utf8_lossy(&name[..name_len]).into_owned());
+        }
+    }
+    let flavor = imported_dlls.iter().filter_map(|dll| {
+        if dll.eq_ignore_ascii_case(Flavor::Cygwin.dll_name()) {
+            Some(Flavor::Cygwin)
+        } else if dll.eq_ignore_ascii_case(Flavor::Msys.dll_name()) {
+            Some(Flavor::Msys)
+        } else {
+            None
+        }
+    }).next();
+    Ok(ExecutableInfo {
+        machine: image.machine,
+        subsystem: Subsystem::from_u16(image.subsystem),
+        flavor,
+        imported_dlls,
+    })
+}
+
+/// Reads the architecture and version of a Cygwin runtime DLL.
+/// Only the headers and the resources are read, not the whole file.
+pub fn read_runtime_info(path: &Path) -> Result<RuntimeInfo, PeError> {
+    let mut file = File::open(path)?;
+    let mut headers = Vec::new();
+    (&mut file).take(MAX_HEADERS_SIZE).read_to_end(&mut headers)?;
+    let image = PeImage::parse(&headers)?;
+    Ok(RuntimeInfo {
+        machine: image.machine,
+        version: image.file_version(&mut |offset, len| read_file_at(&mut file, offset, len))?,
+    })
+}
+
+/// Parses the architecture and version out of the contents of a DLL.
+pub fn parse_runtime_info(data: &[u8]) -> Result<RuntimeInfo, PeError> {
+    let image = PeImage::parse(data)?;
+    Ok(RuntimeInfo {
+        machine: image.machine,
+        version: image.file_version(&mut |offset, len| Ok(read_slice_at(data, offset, len)))?,
+    })
+}
+
+/// Reads `len` bytes at `offset` of `file`, or fewer at its end.
+fn read_file_at(file: &mut File, offset: usize, len: usize) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    file.seek(SeekFrom::Start(offset as u64))?;
+    file.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Returns `len` bytes at `offset` of `data`, or fewer at its end.
+fn read_slice_at(data: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let end = data.len().min(offset.saturating_add(len));
+    data.get(offset..end).unwrap_or(&[]).to_vec()
+}
+
+const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+const MAX_HEADERS_SIZE: u64 = 64 * 1024;
+const MAX_IMPORTS_SIZE: usize = 64 * 1024;
+const MAX_DLL_NAME_SIZE: usize = 260;
+const MAX_RESOURCES_SIZE: usize = 1024 * 1024;
+const RT_VERSION: u32 = 16;
+const VS_FFI_SIGNATURE: u32 = 0xfeef_04bd;
+
+/// Headers of a PE file.
+pub(crate) struct PeImage {
+    pub(crate) machine: Machine,
+    /// `IMAGE_SUBSYSTEM_*` of the optional header.
+    pub(crate) subsystem: u16,
+    /// `(rva, size)` of each data directory.
+    data_directories: Vec<(u32, u32)>,
+    /// `(virtual_address, virtual_size, raw_offset, raw_size)` of each section.
+    sections: Vec<(u32, u32, u32, u32)>,
+}
+
+impl PeImage {
+    /// Parses the headers at the start of `data`, which could be the whole file or only its first bytes.
+    pub(crate) fn parse(data: &[u8]) -> Result<PeImage, PeError> {
+        if !data.starts_with(b"MZ") {
+            return Err(PeError::NotPe);
+        }
+        let pe_offset = read_u32(data, 0x3c).ok_or(PeError::NotPe)? as usize;
+        let coff = pe_offset.checked_add(4).ok_or(PeError::NotPe)?;
+        if data.get(pe_offset..coff) != Some(&b"PE\0\0"[..]) {
+            return Err(PeError::NotPe);
+        }
+        let machine = read_u16(data, coff).ok_or(PeError::Truncated)?;
+        let number_of_sections = read_u16(data, coff + 2).ok_or(PeError::Truncated)? as usize;
+        let optional_header_size = read_u16(data, coff + 16).ok_or(PeError::Truncated)? as usize;
+        let optional = add_offset(coff, 20)?;
+        let data_directories_offset = match read_u16(data, optional).ok_or(PeError::Truncated)? {
+            0x10b => 96,
+            0x20b => 112,
+            _ => return Err(PeError::NotPe),
+        };
+        let subsystem = read_u16(data, add_offset(optional, 68)?).ok_or(PeError::Truncated)?;
+        let number_of_directories = read_u32(data, add_offset(optional, data_directories_offset - 4)?).ok_or(PeError::Truncated)? as usize;
+        let mut data_directories = Vec::with_capacity(number_of_directories.min(16));
+        let section_table = add_offset(optional, optional_header_size)?;
+        for i in 0..number_of_directories.min(16) {
+            let entry = add_offset(optional, data_directories_offset + i * 8)?;
+            if add_offset(entry, 8)? > section_table {
+                break;
+            }
+            let rva = read_u32(data, entry).ok_or(PeError::Truncated)?;
+            let size = read_u32(data, add_offset(entry, 4)?).ok_or(PeError::Truncated)?;
+            data_directories.push((rva, size));
+        }
+        let mut sections = Vec::with_capacity(number_of_sections);
+        for i in 0..number_of_sections {
+            let header = add_offset(section_table, i.checked_mul(40).ok_or(PeError::Truncated)?)?;
+            sections.push((
+                read_u32(data, add_offset(header, 12)?).ok_or(PeError::Truncated)?,
+                read_u32(data, add_offset(header, 8)?).ok_or(PeError::Truncated)?,
+                read_u32(data, add_offset(header, 20)?).ok_or(PeError::Truncated)?,
+                read_u32(data, add_offset(header, 16)?).ok_or(PeError::Truncated)?,
+            ));
+        }
+        Ok(PeImage {
+            machine: Machine::from_u16(machine),
+            subsystem,
+            data_directories,
+            sections,
+        })
+    }
+
+    /// Returns the file offset of a relative virtual address.
+    fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        for &(virtual_address, virtual_size, raw_offset, raw_size) in self.sections.iter() {
+            let size = virtual_size.max(raw_size);
+            if rva >= virtual_address && rva - virtual_address < size {
+                let offset_in_section = rva - virtual_address;
+                if offset_in_section >= raw_size {
+                    return None;
+                }
+                return (raw_offset as usize).checked_add(offset_in_section as usize);
+            }
+        }
+        None
+    }
+
+    /// Returns the contents of a data directory, if present.
+    fn data_directory(&self, index: usize) -> Option<(usize, usize)> {
+        match self.data_directories.get(index) {
+            Some(&(rva, size)) if rva != 0 && size != 0 => self.rva_to_offset(rva).map(|offset| (offset, size as usize)),
+            _ => None,
+        }
+    }
+
+    /// Reads the `VS_FIXEDFILEINFO` file version of the first `VERSIONINFO` resource,
+    /// reading the resources out of the file with `read_at`.
+    pub(crate) fn file_version(&self, read_at: &mut dyn FnMut(usize, usize) -> io::Result<Vec<u8>>)
+            -> Result<FileVersion, PeError> {
+        let (resources_offset, resources_size) =
+            self.data_directory(IMAGE_DIRECTORY_ENTRY_RESOURCE).ok_or(PeError::NoVersionInfo)?;
+        let resources = read_at(resources_offset, resources_size.min(MAX_RESOURCES_SIZE))?;
+        // Resource directories are nested by type, name and language.
+        let by_name = resource_subdirectory(&resources, 0, Some(RT_VERSION))?;
+        let by_language = resource_subdirectory(&resources, by_name, None)?;
+        let data_entry = resource_subdirectory(&resources, by_language, None)?;
+        let data_rva = read_u32(&resources, data_entry).ok_or(PeError::Truncated)?;
+        let data_size = read_u32(&resources, data_entry + 4).ok_or(PeError::Truncated)? as usize;
+        let data_offset = self.rva_to_offset(data_rva).ok_or(PeError::Truncated)?;
+        let version_info = read_at(data_offset, data_size.min(MAX_RESOURCES_SIZE))?;
+        if version_info.len() < data_size.min(MAX_RESOURCES_SIZE) {
+            return Err(PeError::Truncated);
+        }
+        // VS_FIXEDFILEINFO follows the VS_VERSION_INFO key, aligned to 4 bytes.
+        let mut offset = 0;
+        while offset + 16 <= version_info.len() {
+            if read_u32(&version_info, offset) == Some(VS_FFI_SIGNATURE) {
+                let version_ms = read_u32(&version_info, offset + 8).ok_or(PeError::Truncated)?;
+                let version_ls = read_u32(&version_info, offset + 12).ok_or(PeError::Truncated)?;
+                return Ok(FileVersion::new(
+                    (version_ms >> 16) as u16, version_ms as u16,
+                    (version_ls >> 16) as u16, version_ls as u16));
+            }
+            offset += 4;
+        }
+        Err(PeError::NoVersionInfo)
+    }
+}
+
+/// Returns the offset of the entry of a resource directory with the given ID
+/// (or of its first entry, if `None`).
+/// Offsets are relative to the start of the resource tree, `resources`, as the offsets in it are.
+fn resource_subdirectory(resources: &[u8], directory: usize, id: Option<u32>) -> Result<usize, PeError> {
+    let named = read_u16(resources, directory + 12).ok_or(PeError::Truncated)? as usize;
+    let by_id = read_u16(resources, directory + 14).ok_or(PeError::Truncated)? as usize;
+    for i in 0..(named + by_id) {
+        let entry = directory + 16 + i * 8;
+        let name = read_u32(resources, entry).ok_or(PeError::Truncated)?;
+        let target = read_u32(resources, entry + 4).ok_or(PeError::Truncated)?;
+        let matches = match id {
+            None => true,
+            Some(id) => name & 0x8000_0000 == 0 && name == id,
+        };
+        if matches {
+            return Ok((target & 0x7fff_ffff) as usize);
+        }
+    }
+    Err(PeError::NoVersionInfo)
+}
+
+/// Adds to an offset taken from the file, failing instead of overflowing.
+fn add_offset(offset: usize, add: usize) -> Result<usize, PeError> {
+    offset.checked_add(add).ok_or(PeError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24)
+}
+
+#[cfg(test)]
+pub mod fixtures {
+    //! Builders of minimal PE files for tests.
+
+    pub const SECTION_RVA: u32 = 0x1000;
+    const SECTION_OFFSET: usize = 0x200;
+
+    fn put_u16(data: &mut Vec<u8>, offset: usize, value: u16) {
+        if data.len() < offset + 2 { data.resize(offset + 2, 0); }
+        data[offset] = value as u8;
+        data[offset + 1] = (value >> 8) as u8;
+    }
+
+    pub fn put_u32(data: &mut Vec<u8>, offset: usize, value: u32) {
+        put_u16(data, offset, value as u16);
+        put_u16(data, offset + 2, (value >> 16) as u16);
+    }
+
+    /// Builds a PE file with a single section at `SECTION_RVA` holding `section`,
+    /// and the given data directories, as `(index, rva, size)`.
+    pub fn pe_file(machine: u16, pe32_plus: bool, subsystem: u16, directories: &[(usize, u32, u32)], section: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"MZ");
+        put_u32(&mut data, 0x3c, 0x40);
+        data.resize(0x40, 0);
+        data.extend_from_slice(b"PE\0\0");
+        let coff = 0x44;
+        put_u16(&mut data, coff, machine);
+        put_u16(&mut data, coff + 2, 1);
+        let directories_offset = if pe32_plus { 112 } else { 96 };
+        let optional_header_size = directories_offset + 16 * 8;
+        put_u16(&mut data, coff + 16, optional_header_size as u16);
+        let optional = coff + 20;
+        put_u16(&mut data, optional, if pe32_plus { 0x20b } else { 0x10b });
+        put_u16(&mut data, optional + 68, subsystem);
+        put_u32(&mut data, optional + directories_offset - 4, 16);
+        for &(index, rva, size) in directories.iter() {
+            put_u32(&mut data, optional + directories_offset + index * 8, rva);
+            put_u32(&mut data, optional + directories_offset + index * 8 + 4, size);
+        }
+        let section_header = optional + optional_header_size;
+        data.resize(section_header + 40, 0);
+        data[section_header..section_header + 5].copy_from_slice(b".data");
+        put_u32(&mut data, section_header + 8, section.len() as u32);
+        put_u32(&mut data, section_header + 12, SECTION_RVA);
+        put_u32(&mut data, section_header + 16, section.len() as u32);
+        put_u32(&mut data, section_header + 20, SECTION_OFFSET as u32);
+        data.resize(SECTION_OFFSET, 0);
+        data.extend_from_slice(section);
+        data
+    }
+
+    /// Builds an import section, importing the given DLLs.
+    pub fn import_section(dlls: &[&str]) -> Vec<u8> {
+        let mut section = Vec::new();
+        let mut name_offset = (dlls.len() + 1) * 20;
+        for (i, dll) in dlls.iter().enumerate() {
+            put_u32(&mut section, i * 20 + 12, SECTION_RVA + name_offset as u32);
+            section.resize(name_offset, 0);
+            section.extend_from_slice(dll.as_bytes());
+            section.push(0);
+            name_offset = section.len();
+        }
+        section
+    }
+
+    /// Builds a resource section with a single `VERSIONINFO` of the given version.
+    pub fn version_resource_section(version: [u16; 4]) -> Vec<u8> {
+        let mut section = Vec::new();
+        // Type directory, with RT_VERSION pointing to the name directory at 0x18.
+        put_u16(&mut section, 14, 1);
+        put_u32(&mut section, 16, 16);
+        put_u32(&mut section, 20, 0x8000_0018);
+        // Name directory, with ID 1 pointing to the language directory at 0x30.
+        put_u16(&mut section, 0x18 + 14, 1);
+        put_u32(&mut section, 0x18 + 16, 1);
+        put_u32(&mut section, 0x18 + 20, 0x8000_0030);
+        // Language directory, with 0x409 pointing to the data entry at 0x48.
+        put_u16(&mut section, 0x30 + 14, 1);
+        put_u32(&mut section, 0x30 + 16, 0x409);
+        put_u32(&mut section, 0x30 + 20, 0x48);
+        // Data entry, pointing to VS_VERSIONINFO at 0x58.
+        let version_info = 0x58;
+        let version_info_size = 40 + 52;
+        put_u32(&mut section, 0x48, SECTION_RVA + version_info as u32);
+        put_u32(&mut section, 0x48 + 4, version_info_size);
+        put_u16(&mut section, version_info, version_info_size as u16);
+        put_u16(&mut section, version_info + 2, 52);
+        for (i, unit) in "VS_VERSION_INFO".encode_utf16().enumerate() {
+            put_u16(&mut section, version_info + 6 + i * 2, unit);
+        }
+        let fixed = version_info + 40;
+        put_u32(&mut section, fixed, 0xfeef_04bd);
+        put_u32(&mut section, fixed + 8, (version[0] as u32) << 16 | version[1] as u32);
+        put_u32(&mut section, fixed + 12, (version[2] as u32) << 16 | version[3] as u32);
+        section.resize(fixed + 52, 0);
+        section
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+use super::fixtures::*;
+
+#[test]
+fn reads_machine_and_version_of_64bit_dll() {
+    let section = version_resource_section([3, 4, 9, 0]);
+    let dll = pe_file(0x8664, true, 3, &[(IMAGE_DIRECTORY_ENTRY_RESOURCE, SECTION_RVA, section.len() as u32)], &section);
+    let info = parse_runtime_info(&dll).unwrap();
+    assert_eq!(info.machine, Machine::X64);
+    assert!(info.machine.is_64bit());
+    assert_eq!(info.version, FileVersion::new(3, 4, 9, 0));
+    assert_eq!(info.version.to_string(), "3.4.9.0");
+}
+
+#[test]
+fn reads_machine_and_version_of_32bit_dll() {
+    let section = version_resource_section([1, 7, 35, 0]);
+    let dll = pe_file(0x014c, false, 3, &[(IMAGE_DIRECTORY_ENTRY_RESOURCE, SECTION_RVA, section.len() as u32)], &section);
+    let info = parse_runtime_info(&dll).unwrap();
+    assert_eq!(info.machine, Machine::X86);
+    assert_eq!(info.version, FileVersion::new(1, 7, 35, 0));
+}
+
+fn executable(subsystem: u16, dlls: &[&str]) -> Vec<u8> {
+    let section = import_section(dlls);
+    pe_file(0x8664, true, subsystem, &[(IMAGE_DIRECTORY_ENTRY_IMPORT, SECTION_RVA, ((dlls.len() + 1) * 20) as u32)], &section)
+}
+
+#[test]
+fn detects_cygwin_and_msys_programs() {
+    let cygwin = parse_executable_info(&executable(3, &["KERNEL32.dll", "cygwin1.dll"])).unwrap();
+    assert_eq!(cygwin.flavor, Some(Flavor::Cygwin));
+    assert_eq!(cygwin.subsystem, Subsystem::Console);
+    assert_eq!(cygwin.imported_dlls, vec!["KERNEL32.dll", "cygwin1.dll"]);
+    let msys = parse_executable_info(&executable(2, &["MSYS-2.0.DLL"])).unwrap();
+    assert_eq!(msys.flavor, Some(Flavor::Msys));
+    assert_eq!(msys.subsystem, Subsystem::Gui);
+}
+
+#[test]
+fn detects_native_programs() {
+    let native = parse_executable_info(&executable(3, &["KERNEL32.dll", "msvcrt.dll"])).unwrap();
+    assert_eq!(native.flavor, None);
+    let no_imports = parse_executable_info(&pe_file(0x014c, false, 3, &[], &[])).unwrap();
+    assert_eq!(no_imports.imported_dlls, Vec::<String>::new());
+}
+
+#[test]
+fn reads_version_from_files() {
+    let section = version_resource_section([3, 4, 9, 0]);
+    let dll = pe_file(0x8664, true, 3, &[(IMAGE_DIRECTORY_ENTRY_RESOURCE, SECTION_RVA, section.len() as u32)], &section);
+    let path = std::env::temp_dir().join(format!("cygwin_fs-pe-{}.dll", std::process::id()));
+    ::std::fs::write(&path, &dll).unwrap();
+    assert_eq!(read_runtime_info(&path).unwrap().version, FileVersion::new(3, 4, 9, 0));
+    let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn reads_imports_from_files() {
+    let path = std::env::temp_dir().join(format!("cygwin_fs-pe-{}.exe", std::process::id()));
+    ::std::fs::write(&path, executable(3, &["cygwin1.dll"])).unwrap();
+    assert_eq!(is_cygwin_executable(&path), Some(Flavor::Cygwin));
+    assert_eq!(is_cygwin_executable(Path::new("/nonexistent.exe")), None);
+    let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn reports_missing_version_and_garbage() {
+    let dll = pe_file(0x8664, true, 3, &[], &[0; 16]);
+    match parse_runtime_info(&dll) {
+        Err(PeError::NoVersionInfo) => {},
+        other => panic!("{:?}", other),
+    }
+    match parse_runtime_info(b"MZ") {
+        Err(PeError::NotPe) => {},
+        other => panic!("{:?}", other),
+    }
+    let mut truncated = pe_file(0x8664, true, 3, &[], &[]);
+    truncated.truncate(0x50);
+    match parse_runtime_info(&truncated) {
+        Err(PeError::Truncated) => {},
+        other => panic!("{:?}", other),
+    }
+}
+
+}
@@ -0,0 +1,27 @@
+//! Which symlinks `CygRoot::resolve_path()` follows.
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// How many symlinks are followed in a row before giving up, as on a cycle, unless set otherwise
+/// with `CygRoot::set_max_symlink_hops()`. This is the limit of Linux and Cygwin.
+pub const DEFAULT_MAX_SYMLINK_HOPS: usize = 40;
+
+/// Which symlinks of a path are followed, as set with `CygRoot::set_symlink_policy()`,
+/// or given to `CygRoot::resolve_path_with_policy()`.
+///
+/// Paths relative to `CygRoot::cwd()` get `..` applied before looking at symlinks,
+/// so their directories are resolved under every policy but `Never`.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SymlinkPolicy {
+    /// Follow no symlinks, only convert the path, as `lstat` looks at the final component.
+    Never,
+    /// Follow the final component if it is a symlink, as many times as needed, as `stat` does.
+    #[default]
+    FinalOnly,
+    /// Follow the final component if it is a symlink, once, as `readlink` does.
+    FinalOnce,
+    /// Follow symlinks in every directory of the path, and in the final component, as `realpath` does.
+    AllComponents,
+}
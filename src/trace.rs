@@ -0,0 +1,112 @@
+//! Steps `CygRoot::resolve_path_traced()` records, explaining how a path was resolved.
+
+use std::path::PathBuf;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::policy::SymlinkPolicy;
+
+/// One step of resolving a Cygwin path, as recorded by `CygRoot::resolve_path_traced()`.
+///
+/// With the `serde` feature, steps can be serialized, as to attach them to a bug report.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ResolutionStep {
+    /// Not running under Cygwin, so the path was returned as it is.
+    NotUnderCygwin,
+    /// Symlinks were followed as the policy says.
+    Policy(SymlinkPolicy),
+    /// The relative path was joined to the working directory, and `..` applied, giving `path`.
+    RelativeToCwd { cwd: String, path: PathBuf },
+    /// The path was left as it is, being a native one, or a relative one without a working directory.
+    NotConverted(PathBuf),
+    /// The path was to a drive, under the cygdrive prefix.
+    Cygdrive { prefix: String, drive: char },
+    /// The path was under a mount of the native directory.
+    Mount { mount_point: String, native: PathBuf },
+    /// The path was under the Cygwin root.
+    Root(PathBuf),
+    /// The Cygwin path was converted to this native one.
+    Converted(PathBuf),
+    /// The file is not a Cygwin symlink, so it was not followed.
+    NotASymlink(PathBuf),
+    /// The symlink file was read, having the Cygwin path `target`.
+    SymlinkRead { link: PathBuf, target: PathBuf },
+    /// The file could be a symlink, but could not be read as one (as one of a format disabled).
+    SymlinkUnreadable(PathBuf),
+    /// The symlink target was joined to the directory of the symlink, giving this native path.
+    Joined(PathBuf),
+    /// Following symlinks was given up at this path, after `CygRoot::max_symlink_hops()` hops.
+    HopLimit(PathBuf),
+    /// The path was resolved to this one, in `CygRoot::output_style()`.
+    Resolved(PathBuf),
+}
+
+/// Where steps are recorded, if anywhere, as resolution is done by the same code with and without tracing.
+#[cfg(any(windows, test))]
+pub struct Trace<'a> {
+    steps: Option<&'a mut Vec<ResolutionStep>>,
+}
+
+#[cfg(any(windows, test))]
+impl<'a> Trace<'a> {
+    /// Records nothing.
+    pub fn off() -> Trace<'a> {
+        Trace { steps: None }
+    }
+
+    /// Records steps into `steps`.
+    pub fn to(steps: &'a mut Vec<ResolutionStep>) -> Trace<'a> {
+        Trace { steps: Some(steps) }
+    }
+
+    /// Records the step, only making it if recording.
+    pub fn push<F: FnOnce() -> ResolutionStep>(&mut self, step: F) {
+        if let Some(ref mut steps) = self.steps {
+            steps.push(step());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+use std::path::PathBuf;
+
+use super::{ResolutionStep, Trace};
+
+#[test]
+fn records_only_when_on() {
+    let mut made = false;
+    Trace::off().push(|| { made = true; ResolutionStep::NotUnderCygwin });
+    assert!(!made);
+
+    let mut steps = Vec::new();
+    {
+        let mut trace = Trace::to(&mut steps);
+        trace.push(|| ResolutionStep::Converted(PathBuf::from("C:\\cygwin\\tmp")));
+        trace.push(|| ResolutionStep::NotASymlink(PathBuf::from("C:\\cygwin\\tmp")));
+    }
+    assert_eq!(steps, vec![
+        ResolutionStep::Converted(PathBuf::from("C:\\cygwin\\tmp")),
+        ResolutionStep::NotASymlink(PathBuf::from("C:\\cygwin\\tmp")),
+    ]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serializes_steps() {
+    use crate::SymlinkPolicy;
+    let steps = vec![
+        ResolutionStep::Policy(SymlinkPolicy::FinalOnly),
+        ResolutionStep::Mount { mount_point: String::from("/data"), native: PathBuf::from("D:\\data") },
+        ResolutionStep::SymlinkRead { link: PathBuf::from("D:\\data\\x"), target: PathBuf::from("y") },
+    ];
+    let json = ::serde_json::to_string(&steps).unwrap();
+    assert_eq!(json, r#"[{"Policy":"FinalOnly"},{"Mount":{"mount_point":"/data","native":"D:\\data"}},{"SymlinkRead":{"link":"D:\\data\\x","target":"y"}}]"#);
+    let parsed: Vec<ResolutionStep> = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, steps);
+}
+
+}